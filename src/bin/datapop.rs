@@ -8,11 +8,11 @@ const BINK_CLIENT_ID: &str = "MKd3FfDGBi1CIUQwtahmPap64lneCa2R6GvVWKg6dNg4w9Jnpd
 
 #[derive(Deserialize)]
 struct Record {
-    token: String,
-    retailer_slug: String,
-    first_six: String,
-    last_four: String,
-    payment_slug: String,
+    _token: String,
+    _retailer_slug: String,
+    _first_six: String,
+    _last_four: String,
+    _payment_slug: String,
 }
 
 struct User {
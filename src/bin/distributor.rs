@@ -3,7 +3,7 @@ use color_eyre::{eyre::eyre, Result};
 
 use atalanta::configuration::{load_distributor_config, load_settings};
 use atalanta::initialise::startup;
-use atalanta::models::{DistributorConfig, Settings};
+use atalanta::models::{ConsumerKind, DistributorConfig, FormatterKind, SenderConfig, Settings};
 use atalanta::{amqp, consumers, formatters, senders};
 use tracing::info;
 
@@ -56,35 +56,79 @@ where
 }
 
 fn start_distributor(config: DistributorConfig, settings: &Settings) -> Result<()> {
-    macro_rules! init_and_start_consuming {
-        ($consumer:ident, $formatter:ident, $sender:ident) => {
-            init_and_start_consuming::<
-                consumers::$consumer::Consumer,
-                formatters::$formatter::Formatter,
-                senders::$sender::Sender,
-            >(settings, config, None)?
-        };
+    let pipeline = config.pipeline()?;
+    info!(?pipeline.consumer, ?pipeline.formatter, ?pipeline.delay, "starting pipeline");
+    let delay = pipeline.delay.map(Duration::from_std).transpose()?;
+    let config = DistributorConfig {
+        transaction_types: pipeline.transaction_types,
+        ..config
+    };
+
+    match pipeline.consumer {
+        ConsumerKind::Instant => with_formatter::<consumers::instant::Consumer>(
+            settings,
+            config,
+            pipeline.formatter,
+            delay,
+        ),
+        ConsumerKind::Batch => with_formatter::<consumers::batch::Consumer>(
+            settings,
+            config,
+            pipeline.formatter,
+            delay,
+        ),
+        ConsumerKind::Delay => with_formatter::<consumers::delay::Consumer>(
+            settings,
+            config,
+            pipeline.formatter,
+            delay,
+        ),
+    }
+}
 
-        ($consumer:ident, $formatter:ident, $sender:ident, $delay_seconds:expr) => {
-            init_and_start_consuming::<
-                consumers::$consumer::Consumer,
-                formatters::$formatter::Formatter,
-                senders::$sender::Sender,
-            >(settings, config, Some(Duration::seconds($delay_seconds)))?
+fn with_formatter<C: consumers::Consumer>(
+    settings: &Settings,
+    config: DistributorConfig,
+    formatter: FormatterKind,
+    delay: Option<Duration>,
+) -> Result<()> {
+    macro_rules! with_sender {
+        ($formatter:ident) => {
+            with_sender::<C, formatters::$formatter::Formatter>(settings, config, delay)
         };
     }
 
-    match config.provider_slug.as_str() {
-        "costa" => init_and_start_consuming!(instant, costa, api),
-        "stonegate" => init_and_start_consuming!(instant, stonegate, api),
-        "tgi-fridays" => init_and_start_consuming!(instant, tgi_fridays, blob),
-        "wasabi-club" => init_and_start_consuming!(batch, wasabi, sftp),
-        "iceland-bonus-card" => init_and_start_consuming!(batch, iceland, blob),
-        "visa-auth" => init_and_start_consuming!(instant, visa_auth, api),
-        "visa-settlement" => init_and_start_consuming!(delay, visa_settlement, api, 10),
-        "amex-auth" => init_and_start_consuming!(instant, amex_auth, amex),
-        _ => return Err(eyre!("No process available for {}", config.provider_slug)),
+    match formatter {
+        FormatterKind::Costa => with_sender!(costa),
+        FormatterKind::Stonegate => with_sender!(stonegate),
+        FormatterKind::TgiFridays => with_sender!(tgi_fridays),
+        FormatterKind::Wasabi => with_sender!(wasabi),
+        FormatterKind::Iceland => with_sender!(iceland),
+        FormatterKind::VisaAuth => with_sender!(visa_auth),
+        FormatterKind::VisaSettlement => with_sender!(visa_settlement),
+        FormatterKind::AmexAuth => with_sender!(amex_auth),
+        FormatterKind::AmexSettlement => with_sender!(amex_settlement),
     }
+}
 
-    Ok(())
+/// Picks the sender that the `sender` config is for.
+fn with_sender<C: consumers::Consumer, F: formatters::Formatter>(
+    settings: &Settings,
+    config: DistributorConfig,
+    delay: Option<Duration>,
+) -> Result<()> {
+    match config.sender {
+        SenderConfig::API(_) => {
+            init_and_start_consuming::<C, F, senders::api::Sender>(settings, config, delay)
+        }
+        SenderConfig::Amex(_) => {
+            init_and_start_consuming::<C, F, senders::amex::Sender>(settings, config, delay)
+        }
+        SenderConfig::SFTP(_) => {
+            init_and_start_consuming::<C, F, senders::sftp::Sender>(settings, config, delay)
+        }
+        SenderConfig::Blob(_) => {
+            init_and_start_consuming::<C, F, senders::blob::Sender>(settings, config, delay)
+        }
+    }
 }
@@ -0,0 +1,33 @@
+use std::io::{self, BufWriter};
+
+use color_eyre::Result;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use tracing::info;
+
+use atalanta::generate::mids::{generate_identifiers, write_identifiers};
+use atalanta::generate::Command;
+use atalanta::initialise::startup;
+
+const COMMAND: Command = Command {
+    usage: "usage: midgen <stores per retailer> <retailer>... \
+            [--providers visa,mastercard,amex] [--seed <seed>]",
+    count_name: "store",
+    schemes_option: "--providers",
+    options: &[],
+};
+
+fn main() -> Result<()> {
+    startup()?;
+
+    let args = COMMAND.parse(std::env::args().skip(1))?;
+    let mut rng = args
+        .seed
+        .map_or_else(StdRng::from_entropy, StdRng::seed_from_u64);
+
+    let records = generate_identifiers(&args.retailer_slugs, &args.schemes, args.count, &mut rng)?;
+    write_identifiers(&records, BufWriter::new(io::stdout().lock()))?;
+    info!(count = records.len(), "generated identifiers");
+
+    Ok(())
+}
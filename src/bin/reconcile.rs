@@ -0,0 +1,27 @@
+use std::path::PathBuf;
+
+use color_eyre::{eyre::eyre, Result};
+use tracing::info;
+
+use atalanta::initialise::startup;
+use atalanta::reconcile::{read_export, read_ledger, Reconciliation};
+
+const USAGE: &str = "usage: reconcile <ledger> <harmonia export> [run id]";
+
+fn main() -> Result<()> {
+    startup()?;
+
+    let mut args = std::env::args().skip(1);
+    let ledger_path = args.next().map(PathBuf::from).ok_or_else(|| eyre!(USAGE))?;
+    let export_path = args.next().map(PathBuf::from).ok_or_else(|| eyre!(USAGE))?;
+    let run_id = args.next();
+
+    let ledger = read_ledger(&ledger_path, run_id.as_deref())?;
+    info!(entries = ledger.len(), path = ?ledger_path, "read ledger");
+    let exported = read_export(&export_path)?;
+    info!(records = exported.len(), path = ?export_path, "read Harmonia export");
+
+    println!("{}", Reconciliation::new(&ledger, &exported));
+
+    Ok(())
+}
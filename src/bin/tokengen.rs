@@ -0,0 +1,49 @@
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::PathBuf;
+
+use chrono::Utc;
+use color_eyre::{eyre::eyre, Result};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use tracing::info;
+
+use atalanta::generate::tokens::{generate_cards, write_cards, write_tokens};
+use atalanta::generate::Command;
+use atalanta::initialise::startup;
+
+const COMMAND: Command = Command {
+    usage: "usage: tokengen <tokens per retailer and scheme> <retailer>... \
+            [--schemes visa,mastercard,amex] [--seed <seed>] [--cards <path>]",
+    count_name: "token",
+    schemes_option: "--schemes",
+    options: &["--cards"],
+};
+
+fn main() -> Result<()> {
+    startup()?;
+
+    let mut args = COMMAND.parse(std::env::args().skip(1))?;
+    let mut rng = args
+        .seed
+        .map_or_else(StdRng::from_entropy, StdRng::seed_from_u64);
+
+    let cards = generate_cards(
+        &args.retailer_slugs,
+        &args.schemes,
+        args.count,
+        Utc::now().date_naive(),
+        &mut rng,
+    );
+    write_tokens(&cards, BufWriter::new(io::stdout().lock()))?;
+    info!(count = cards.len(), "generated tokens");
+
+    if let Some(path) = args.options.remove("--cards").map(PathBuf::from) {
+        let file = File::create(&path)
+            .map_err(|e| eyre!("failed to create cards file {}: {e}", path.display()))?;
+        write_cards(&cards, BufWriter::new(file))?;
+        info!(path = ?path, "wrote card numbers and expiry dates");
+    }
+
+    Ok(())
+}
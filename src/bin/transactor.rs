@@ -1,20 +1,40 @@
-use amiquip::{Exchange, ExchangeDeclareOptions, ExchangeType, Publish};
-use atalanta::amqp;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use color_eyre::{eyre::eyre, Result};
 use rand::distributions::WeightedIndex;
 use rand::prelude::*;
+use rand::rngs::StdRng;
 use rand::Rng;
-use serde::{Deserialize, Serialize};
-use std::fs::File;
-use std::path::Path;
-use std::time;
-use tracing::info;
-use uuid::Uuid;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+use uuid::{Builder, Uuid};
 
 use atalanta::configuration::{load_settings, load_transactor_config};
 use atalanta::initialise::startup;
-use atalanta::models::{Settings, Transaction, TransactorConfig};
+use atalanta::models::{
+    Currency, ReplayConfig, RetailerConfig, Settings, SinkConfig, Transaction, TransactionType,
+    TransactorConfig,
+};
+use atalanta::transactor::amount::AmountSampler;
+use atalanta::transactor::clock::Clock;
+use atalanta::transactor::control::{self, Control};
+use atalanta::transactor::injection::{Injection, Injector};
+use atalanta::transactor::ledger::{Expectation, Ledger};
+use atalanta::transactor::lifecycle::{Lifecycles, Settlement};
+use atalanta::transactor::metrics;
+use atalanta::transactor::pacer::{RateReporter, TokenBucket};
+use atalanta::transactor::pool::{
+    load_identifier_pools, load_token_pools, IdentifierPool, IdentifierRecord, IdentifierType,
+    TokenPool, TokenRecord,
+};
+use atalanta::transactor::profile::{LoadProfile, PhaseMonitor, Target};
+use atalanta::transactor::replay::{read_transactions, ReplayPacer};
+use atalanta::transactor::reversal::Reversals;
+use atalanta::transactor::sink::{Sink, Sinks};
+use atalanta::transactor::summary::RunSummary;
+use atalanta::transactor::timestamps::TimestampShaper;
 
 fn main() -> Result<()> {
     info!("starting transactor");
@@ -22,277 +42,840 @@ fn main() -> Result<()> {
 
     let settings = load_settings()?;
     let config = load_transactor_config(&settings)?;
+    if let Some(metrics_config) = &config.metrics {
+        metrics::serve(metrics_config)?;
+    }
+    if let Some(replay) = &config.replay {
+        return replay_transactions(replay, &config, &settings);
+    }
+
+    let mut retailer_slugs = HashSet::new();
+    for retailer in &config.retailers {
+        if !retailer_slugs.insert(retailer.provider_slug.as_str()) {
+            return Err(eyre!(
+                "retailer {} is configured more than once",
+                retailer.provider_slug
+            ));
+        }
+    }
+
+    let mut payment_card_tokens = load_token_pools(&settings.tokens_file_path, &retailer_slugs)?;
+    let mut identifiers = load_identifier_pools(&settings.mids_file_path, &retailer_slugs)?;
 
-    let payment_card_tokens =
-        load_payment_card_tokens(&config.provider_slug, &settings.tokens_file_path)?;
-    let identifiers = load_retailer_identifiers(&config.provider_slug, &settings.mids_file_path)?;
+    let mut retailers = config
+        .retailers
+        .iter()
+        .map(|retailer| {
+            Retailer::new(
+                retailer,
+                payment_card_tokens
+                    .remove(&retailer.provider_slug)
+                    .unwrap_or_default(),
+                identifiers
+                    .by_retailer
+                    .remove(&retailer.provider_slug)
+                    .unwrap_or_default(),
+            )
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    for retailer in &retailers {
+        retailer.validate()?;
+    }
+
+    let checks = retailers
+        .iter()
+        .map(|retailer| {
+            (
+                retailer.config.provider_slug.clone(),
+                retailer.percentage_check(),
+            )
+        })
+        .collect::<HashMap<_, _>>();
+    let control = Arc::new(Control::new(retailer_slugs).with_percentage_check(
+        move |slug, percentage| checks.get(slug).map_or(Ok(()), |check| check(percentage)),
+    ));
+    if let Some(control_config) = &config.control {
+        control::serve(control_config, Arc::clone(&control))?;
+    }
 
-    transaction_producer(&config, &settings, &payment_card_tokens, &identifiers)
+    transaction_producer(
+        &config,
+        &settings,
+        &mut retailers,
+        identifiers.known,
+        &control,
+    )
 }
 
-#[derive(Debug, Deserialize)]
-struct TokenRecord {
-    token: String,
-    retailer_slug: String,
-    first_six: String,
-    last_four: String,
-    payment_slug: String,
+/// A retailer's config along with the tokens, identifiers, amount distributions and timestamp
+/// shaping loaded for it.
+struct Retailer<'a> {
+    config: &'a RetailerConfig,
+    /// The payment provider percentages, which start as configured and can be changed while the
+    /// transactor runs.
+    percentage: Vec<(String, u32)>,
+    /// Shared with the control API, which checks new percentages against them.
+    tokens: Arc<TokenPool>,
+    identifiers: Arc<IdentifierPool>,
+    amounts: AmountSampler,
+    provider_amounts: HashMap<String, AmountSampler>,
+    timestamps: Option<TimestampShaper>,
 }
 
-fn load_payment_card_tokens(
-    retailer_slug: &str,
-    tokens_file_path: &Path,
-) -> Result<Vec<TokenRecord>> {
-    // Load token and slugs derived from the Hermes database
-    //Only tokens related to the current retailer are loaded
-    let mut tokens = Vec::new();
-
-    let file = File::open(tokens_file_path)?;
-    let mut rdr = csv::ReaderBuilder::new()
-        .has_headers(false)
-        .from_reader(file);
-
-    for result in rdr.deserialize() {
-        let record: TokenRecord = result?;
-        if record.retailer_slug == retailer_slug {
-            tokens.push(record);
-        }
+impl<'a> Retailer<'a> {
+    fn new(
+        config: &'a RetailerConfig,
+        tokens: TokenPool,
+        identifiers: IdentifierPool,
+    ) -> Result<Self> {
+        let slug = &config.provider_slug;
+        let amounts = config.amount_distribution().ok_or_else(|| {
+            eyre!("retailer {slug} needs either amounts or amount_min and amount_max")
+        })?;
+        let amounts = AmountSampler::from_config(&amounts)
+            .map_err(|e| eyre!("invalid amounts for retailer {slug}: {e}"))?;
+
+        let provider_amounts = config
+            .provider_amounts
+            .iter()
+            .map(|(provider, amounts)| {
+                AmountSampler::from_config(amounts)
+                    .map(|sampler| (provider.clone(), sampler))
+                    .map_err(|e| eyre!("invalid {provider} amounts for retailer {slug}: {e}"))
+            })
+            .collect::<Result<_>>()?;
+
+        let timestamps = config
+            .timestamps
+            .as_ref()
+            .map(TimestampShaper::from_config)
+            .transpose()
+            .map_err(|e| eyre!("invalid timestamps for retailer {slug}: {e}"))?;
+
+        Ok(Self {
+            config,
+            percentage: config.percentage.clone(),
+            tokens: Arc::new(tokens),
+            identifiers: Arc::new(identifiers),
+            amounts,
+            provider_amounts,
+            timestamps,
+        })
     }
 
-    info!(
-        "loaded {} tokens from {} for retailer {retailer_slug}",
-        tokens.len(),
-        tokens_file_path.display()
-    );
+    /// Returns the amount distribution for a payment provider.
+    fn amounts(&self, payment_provider: &str) -> &AmountSampler {
+        self.provider_amounts
+            .get(payment_provider)
+            .unwrap_or(&self.amounts)
+    }
 
-    Ok(tokens)
-}
+    fn has_identifier(&self, identifier: &str) -> bool {
+        self.identifiers.contains(identifier)
+    }
+
+    /// Works out whether a transaction generated for this retailer should match.
+    fn expect(&self, transaction: &Transaction, injection: Option<Injection>) -> Expectation {
+        Expectation::new(
+            self.tokens.contains(&transaction.token),
+            self.has_identifier(&transaction.identifier),
+            injection,
+        )
+    }
 
-#[derive(Debug, Deserialize, Serialize)]
-enum IdentifierType {
-    #[serde(rename = "PRIMARY")]
-    PrimaryMID,
-    #[serde(rename = "SECONDARY")]
-    SecondaryMID,
-    #[serde(rename = "PSIMI")]
-    Psimi,
+    /// Switches to new payment provider percentages, keeping the old ones if the new ones fail
+    /// validation.
+    fn set_percentage(&mut self, percentage: Vec<(String, u32)>) -> Result<()> {
+        let previous = std::mem::replace(&mut self.percentage, percentage);
+        self.validate().inspect_err(|_| self.percentage = previous)
+    }
+
+    /// Checks that every payment scheme and identifier type the retailer is configured to use
+    /// has tokens and identifiers to choose from.
+    fn validate(&self) -> Result<()> {
+        validate_percentage(
+            self.config,
+            &self.tokens,
+            &self.identifiers,
+            &self.percentage,
+        )
+    }
+
+    /// Returns a check of new payment provider percentages for this retailer, which can be run
+    /// from another thread.
+    fn percentage_check(&self) -> impl Fn(&[(String, u32)]) -> Result<()> + Send + Sync {
+        let config = self.config.clone();
+        let tokens = Arc::clone(&self.tokens);
+        let identifiers = Arc::clone(&self.identifiers);
+        move |percentage| validate_percentage(&config, &tokens, &identifiers, percentage)
+    }
 }
 
-impl ToString for IdentifierType {
-    fn to_string(&self) -> String {
-        match self {
-            Self::PrimaryMID => "PRIMARY".to_owned(),
-            Self::SecondaryMID => "SECONDARY".to_owned(),
-            Self::Psimi => "PSIMI".to_owned(),
+/// Checks that every payment scheme weighted in `percentage`, and the identifier types the
+/// retailer is configured to use, have tokens and identifiers to choose from.
+fn validate_percentage(
+    config: &RetailerConfig,
+    tokens: &TokenPool,
+    identifiers: &IdentifierPool,
+    percentage: &[(String, u32)],
+) -> Result<()> {
+    let slug = &config.provider_slug;
+    let schemes = percentage
+        .iter()
+        .filter(|(_, weight)| *weight > 0)
+        .map(|(scheme, _)| scheme)
+        .collect::<Vec<_>>();
+
+    if schemes.is_empty() {
+        return Err(eyre!(
+            "retailer {slug} has no payment schemes with a weight"
+        ));
+    }
+
+    let currencies = &config.currencies;
+    if !currencies.is_empty() && currencies.iter().all(|(_, weight)| *weight == 0) {
+        return Err(eyre!("retailer {slug} has no currencies with a weight"));
+    }
+
+    for scheme in schemes {
+        if !tokens.has_provider(scheme) {
+            return Err(eyre!(
+                "retailer {slug} is configured for {scheme}, but the tokens file has no {scheme} tokens for it"
+            ));
+        }
+
+        if !identifiers.has_provider(scheme) {
+            return Err(eyre!(
+                "retailer {slug} is configured for {scheme}, but the MIDs file has no {scheme} identifiers for it"
+            ));
+        }
+
+        if let Some(weights) = &config.identifier_type_percentage {
+            if weighted_identifier_types(identifiers, scheme, weights).is_empty() {
+                return Err(eyre!(
+                    "retailer {slug} has no {scheme} identifiers of a weighted identifier type"
+                ));
+            }
         }
     }
+
+    Ok(())
 }
 
-#[derive(Debug, Deserialize)]
-struct IdentifierRecord {
-    retailer_slug: String,
-    payment_slug: String,
-    identifier: String,
-    identifier_type: IdentifierType,
-    _location_id: Option<String>,
-    _merchant_internal_id: Option<String>,
+/// The longest the producer sleeps before checking the load profile again.
+const IDLE_DELAY: Duration = Duration::from_millis(100);
+
+/// The most time's worth of transactions generated in one burst.
+const BURST: Duration = Duration::from_millis(10);
+
+/// The most burst transactions, asked for through the control API, generated at once.
+const MAX_BURST: u64 = 1000;
+
+/// How many bursts may wait for each publish worker before the producer blocks.
+const WORKER_QUEUE_DEPTH: usize = 16;
+
+/// How often the configured and achieved publish rates are logged.
+const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Follows the load profile, or whatever has replaced it through the control API, and paces
+/// transactions to its target rate.
+struct Pacing {
+    profile: LoadProfile,
+    started: Instant,
+    bucket: TokenBucket,
+    monitor: PhaseMonitor,
+    reporter: RateReporter,
 }
 
-fn load_retailer_identifiers(
-    retailer_slug: &str,
-    mids_file_path: &Path,
-) -> Result<Vec<IdentifierRecord>> {
-    //Only identifiers related to the current retailer are loaded
-    let mut identifiers = Vec::new();
-
-    let file = File::open(mids_file_path)?;
-    let mut rdr = csv::ReaderBuilder::new()
-        .has_headers(false)
-        .from_reader(file);
-
-    for result in rdr.deserialize() {
-        let record: IdentifierRecord = result?;
-        if record.retailer_slug == retailer_slug {
-            identifiers.push(record);
+impl Pacing {
+    fn new(profile: LoadProfile) -> Self {
+        Self {
+            profile,
+            started: Instant::now(),
+            bucket: TokenBucket::new(BURST),
+            monitor: PhaseMonitor::new(),
+            reporter: RateReporter::new(REPORT_INTERVAL),
         }
     }
 
-    info!(
-        "loaded {} identifiers from {} for retailer {retailer_slug}",
-        identifiers.len(),
-        mids_file_path.display()
-    );
+    /// Returns the profile's current target, or `None` once it has finished. A replacement
+    /// profile starts from its first phase.
+    fn target(&mut self, control: &Control, clock: &mut Clock) -> Option<Target> {
+        if let Some(profile) = control.take_profile() {
+            self.monitor.finish(&self.profile);
+            self.profile = profile;
+            self.started = Instant::now();
+            clock.restart();
+        }
+        self.profile.target(self.started.elapsed())
+    }
 
-    Ok(identifiers)
+    /// Waits for the profile to allow more transactions, and returns how many to generate,
+    /// including any burst asked for.
+    fn acquire(&mut self, target: Target, control: &Control, published: &AtomicU64) -> u64 {
+        let paused = control.is_paused();
+        let target = Target {
+            rate: if paused { 0.0 } else { target.rate },
+            ..target
+        };
+        self.monitor.observe(&self.profile, target);
+        self.reporter.observe(target.rate, published);
+
+        // Burst transactions go out straight away, alongside whatever the profile allows.
+        let burst = control.take_burst(MAX_BURST);
+        let max_wait = if burst == 0 {
+            IDLE_DELAY
+        } else {
+            Duration::ZERO
+        };
+        let waiting = Instant::now();
+        let count = self.bucket.acquire(target.rate, max_wait) + burst;
+        if paused {
+            // The profile stands still while paused, and picks up where it left off.
+            self.started += waiting.elapsed();
+        }
+        count
+    }
+
+    fn finish(&mut self, published: &AtomicU64) {
+        self.monitor.finish(&self.profile);
+        self.reporter.finish(published);
+    }
 }
 
+/// A generated transaction with its routing key and whether it should match.
+struct Generated {
+    transaction: Transaction,
+    routing_key: String,
+    expectation: Expectation,
+}
+
+type Batch = Vec<Generated>;
+
 fn transaction_producer(
     config_data: &TransactorConfig,
     settings: &Settings,
-    payment_card_tokens: &[TokenRecord],
-    identifiers: &[IdentifierRecord],
+    retailers: &mut [Retailer],
+    known_identifiers: HashSet<String>,
+    control: &Control,
 ) -> Result<()> {
     //Manages the process of creating raw transactions
-    let delay = time::Duration::from_millis(1000 / config_data.transactions_per_second);
-    println!("Delay setting - TODO: {}", delay.as_millis());
+    let mut pacing = Pacing::new(LoadProfile::from_config(config_data)?);
+    let retailer_weights = WeightedIndex::new(retailers.iter().map(|r| r.config.weight()))
+        .map_err(|e| eyre!("invalid retailer weights: {e}"))?;
+    let published = AtomicU64::new(0);
+    let mut summary = RunSummary::new();
+    let mut rng = create_rng(config_data.seed);
+    let mut clock = create_clock(config_data);
+    let mut injector =
+        Injector::from_config(&config_data.injection)?.with_known_identifiers(known_identifiers);
+    let mut reversals = Reversals::from_config(&config_data.retailers)?;
+    let mut lifecycles = Lifecycles::from_config(&config_data.retailers)?;
+    let ledger = open_ledger(config_data)?;
 
-    let mut tx: Transaction;
+    let mut sinks = Sinks::open(
+        &config_data.sink,
+        settings,
+        config_data.publish_workers,
+        config_data.max_in_flight,
+    )?;
 
-    let mut connection = amqp::connect(settings)?;
+    std::thread::scope(|scope| -> Result<()> {
+        let (senders, workers): (Vec<_>, Vec<_>) = sinks
+            .sinks
+            .iter_mut()
+            .map(|sink| {
+                let (sender, receiver) = mpsc::sync_channel::<Batch>(WORKER_QUEUE_DEPTH);
+                let published = &published;
+                let ledger = ledger.as_ref();
+                let worker = scope
+                    .spawn(move || publish_worker(sink.as_mut(), &receiver, published, ledger));
+                (sender, worker)
+            })
+            .unzip();
 
-    // Open a channel - None says let the library choose the channel ID.
-    let channel = connection.open_channel(None)?;
+        let started = Instant::now();
+        let deadline = config_data.duration_secs.map(Duration::from_secs);
+        let mut remaining = config_data.total_transactions;
+        let mut next_worker = 0;
+        while let Some(target) = pacing.target(control, &mut clock) {
+            if deadline.is_some_and(|deadline| started.elapsed() >= deadline) {
+                info!("duration limit reached");
+                break;
+            }
+            if remaining == Some(0) {
+                info!("transaction limit reached");
+                break;
+            }
 
-    // Get a handle to the direct exchange on our channel.
-    // let exchange = Exchange::direct(&channel);
-    let exchange = channel.exchange_declare(
-        ExchangeType::Topic,
-        "transactions",
-        ExchangeDeclareOptions::default(),
-    )?;
+            apply_percentages(control, retailers);
 
-    loop {
-        // Select a payment provider based on weighted selection,
-        // visa provides many more transactions than mastercard or amex
-        let payment_provider = select_payment_provider(&config_data.percentage)?;
-        let routing_key = format!(
-            "transactions.{}.{}",
-            payment_provider, config_data.provider_slug
-        );
+            let mut count = pacing.acquire(target, control, &published);
+            if let Some(remaining) = remaining.as_mut() {
+                count = count.min(*remaining);
+                *remaining -= count;
+            }
+            if count == 0 {
+                continue;
+            }
 
-        //Select a token to use for this payment provider, along with first six and last four
-        //This could be an inefficient process since we have to look through a list of StringRecords
-        let payment_details = select_payment_details(payment_card_tokens, &payment_provider);
-        let identifier_details =
-            select_identifiers_per_payment_provider(identifiers, &payment_provider);
-        tx = create_transaction(
-            config_data,
-            &payment_provider,
-            &payment_details,
-            &identifier_details,
-        )?;
+            let batch = (0..count)
+                .map(|_| {
+                    let transaction_date = clock.tick(&pacing.profile);
+                    if let Some(settlement) = lifecycles.next_due(transaction_date) {
+                        return settle(settlement, retailers, &mut reversals);
+                    }
+                    let retailer = &retailers[retailer_weights.sample(&mut rng)];
+                    next_transaction(
+                        retailer,
+                        &mut reversals,
+                        &mut injector,
+                        &mut lifecycles,
+                        &mut rng,
+                        transaction_date,
+                    )
+                })
+                .collect::<Result<Batch>>()?;
+            for generated in &batch {
+                summary.record(&generated.transaction, generated.expectation.injection);
+            }
+
+            // A closed channel means the worker has stopped, and its error is reported on join.
+            if senders[next_worker].send(batch).is_err() {
+                break;
+            }
+            next_worker = (next_worker + 1) % senders.len();
+            pacing.monitor.record_publish(count);
+        }
+
+        drop(senders);
+        for worker in workers {
+            worker
+                .join()
+                .map_err(|_| eyre!("publish worker panicked"))??;
+        }
+
+        Ok(())
+    })?;
+
+    pacing.finish(&published);
+    lifecycles.finish();
+    close_ledger(ledger)?;
+    info!("transactor finished");
+
+    sinks.close()?;
+
+    print_summary(&summary, &config_data.sink);
+
+    Ok(())
+}
+
+/// Switches retailers to any payment provider percentages changed through the control API.
+fn apply_percentages(control: &Control, retailers: &mut [Retailer]) {
+    for (slug, percentage) in control.take_percentages() {
+        let Some(retailer) = retailers
+            .iter_mut()
+            .find(|retailer| retailer.config.provider_slug == slug)
+        else {
+            continue;
+        };
+        if let Err(e) = retailer.set_percentage(percentage) {
+            warn!("keeping the payment provider percentages for {slug}: {e}");
+        }
+    }
+}
+
+/// Publishes captured transactions instead of generating them, with their original timing or at a
+/// fixed rate.
+fn replay_transactions(
+    config: &ReplayConfig,
+    config_data: &TransactorConfig,
+    settings: &Settings,
+) -> Result<()> {
+    let sink = &config_data.sink;
+    let mut pacer = ReplayPacer::from_config(config)?;
+    let transactions = read_transactions(&config.path)?;
+    info!(count = transactions.len(), path = ?config.path, "replaying transactions");
+
+    let mut sinks = Sinks::open(sink, settings, 1, config_data.max_in_flight)?;
+    let publisher = sinks.sinks[0].as_mut();
+
+    let mut summary = RunSummary::new();
+    let started = Instant::now();
+    for transaction in &transactions {
+        if let Some(wait) = pacer.due(transaction).checked_sub(started.elapsed()) {
+            std::thread::sleep(wait);
+        }
+        publish(publisher, transaction, &transaction.routing_key())?;
+        summary.record(transaction, None);
+    }
+    publisher.flush()?;
+
+    info!(elapsed = ?started.elapsed(), "replay finished");
+    sinks.close()?;
+
+    print_summary(&summary, sink);
+
+    Ok(())
+}
+
+/// Prints the summary, to stderr if stdout is taken by transactions.
+fn print_summary(summary: &RunSummary, sink: &SinkConfig) {
+    if matches!(sink, SinkConfig::Stdout) {
+        eprintln!("{summary}");
+    } else {
+        println!("{summary}");
+    }
+}
+
+/// Opens the ledger, if the config asks for one, to be shared by the publish workers.
+fn open_ledger(config_data: &TransactorConfig) -> Result<Option<Mutex<Ledger>>> {
+    let Some(config) = &config_data.ledger else {
+        return Ok(None);
+    };
+    let run_id = config
+        .run_id
+        .clone()
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    info!(run_id, path = ?config.path, "recording published transactions in the ledger");
+
+    Ok(Some(Mutex::new(Ledger::open(config, run_id)?)))
+}
+
+fn close_ledger(ledger: Option<Mutex<Ledger>>) -> Result<()> {
+    let Some(ledger) = ledger else {
+        return Ok(());
+    };
+    let mut ledger = ledger
+        .into_inner()
+        .map_err(|_| eyre!("ledger lock poisoned"))?;
+    ledger.flush()?;
+    info!(run_id = ledger.run_id(), "ledger written");
+    Ok(())
+}
+
+/// Creates the random number generator that drives transaction generation.
+/// A seeded generator produces the same transactions on every run.
+fn create_rng(seed: Option<u64>) -> StdRng {
+    seed.map_or_else(StdRng::from_entropy, |seed| {
+        info!(seed, "using seeded transaction generation");
+        StdRng::seed_from_u64(seed)
+    })
+}
+
+fn create_clock(config_data: &TransactorConfig) -> Clock {
+    match (config_data.start_date, config_data.seed) {
+        (Some(start_date), _) => Clock::simulated(start_date),
+        (None, Some(_)) => {
+            warn!("seed is set without start_date, so transaction dates follow the wall clock");
+            Clock::Wall
+        }
+        (None, None) => Clock::Wall,
+    }
+}
+
+/// Generates a retailer's next transaction: either a reversal of one of its earlier purchases or
+/// a new purchase, possibly with a fault injected into it. Purchases may be sent as an auth, with
+/// their settlement scheduled for later.
+fn next_transaction(
+    retailer: &Retailer,
+    reversals: &mut Reversals,
+    injector: &mut Injector,
+    lifecycles: &mut Lifecycles,
+    rng: &mut impl Rng,
+    transaction_date: DateTime<Utc>,
+) -> Result<Generated> {
+    let slug = &retailer.config.provider_slug;
+    let mut transaction = match reversals.reverse(slug, transaction_date, rng) {
+        Some(reversal) => reversal,
+        None => generate_transaction(retailer, rng, transaction_date)?,
+    };
+
+    let injection = injector.inject(&mut transaction, rng);
+
+    if transaction.transaction_type == TransactionType::Purchase {
+        if lifecycles.applies(slug, &transaction.payment_provider) {
+            lifecycles.authorise(&mut transaction, injection, transaction_date, rng);
+        } else if injection.is_none() {
+            // Only purchases that should match are worth reversing.
+            reversals.remember(&transaction);
+        }
+    }
 
-        queue_transaction(&exchange, &tx, &routing_key)?;
+    Ok(Generated {
+        routing_key: transaction.routing_key(),
+        expectation: retailer.expect(&transaction, injection),
+        transaction,
+    })
+}
 
-        std::thread::sleep(delay);
+/// Sends a settlement that has come due, which can then be reversed like a purchase.
+fn settle(
+    settlement: Settlement,
+    retailers: &[Retailer],
+    reversals: &mut Reversals,
+) -> Result<Generated> {
+    let transaction = settlement.transaction;
+    let retailer = retailers
+        .iter()
+        .find(|retailer| retailer.config.provider_slug == transaction.merchant_name)
+        .ok_or_else(|| {
+            eyre!(
+                "settlement for unknown retailer {}",
+                transaction.merchant_name
+            )
+        })?;
+    if settlement.injection.is_none() {
+        reversals.remember(&transaction);
     }
+
+    Ok(Generated {
+        routing_key: transaction.routing_key(),
+        expectation: retailer.expect(&transaction, settlement.injection),
+        transaction,
+    })
 }
 
-fn select_payment_details<'a>(
-    payment_card_tokens: &'a [TokenRecord],
+fn generate_transaction(
+    retailer: &Retailer,
+    rng: &mut impl Rng,
+    transaction_date: DateTime<Utc>,
+) -> Result<Transaction> {
+    // Select a payment provider based on weighted selection,
+    // visa provides many more transactions than mastercard or amex
+    let payment_provider = select_payment_provider(&retailer.percentage, rng)?;
+
+    //Select a token to use for this payment provider, along with first six and last four
+    let token = retailer
+        .tokens
+        .choose(&payment_provider, rng)
+        .ok_or_else(|| eyre!("failed to select payment card token"))?;
+    let identifier_type = match &retailer.config.identifier_type_percentage {
+        Some(weights) => Some(select_identifier_type(
+            &retailer.identifiers,
+            &payment_provider,
+            weights,
+            rng,
+        )?),
+        None => None,
+    };
+    let identifier = retailer
+        .identifiers
+        .choose(&payment_provider, identifier_type, rng)
+        .ok_or_else(|| eyre!("failed to select identifier"))?;
+    let transaction_date = retailer
+        .timestamps
+        .as_ref()
+        .map_or(transaction_date, |shaper| {
+            shaper.shape(transaction_date, rng)
+        });
+    create_transaction(
+        retailer,
+        &payment_provider,
+        token,
+        identifier,
+        rng,
+        transaction_date,
+    )
+}
+
+fn publish_worker(
+    sink: &mut dyn Sink,
+    batches: &mpsc::Receiver<Batch>,
+    published: &AtomicU64,
+    ledger: Option<&Mutex<Ledger>>,
+) -> Result<()> {
+    for batch in batches {
+        for generated in &batch {
+            publish(sink, &generated.transaction, &generated.routing_key)?;
+        }
+        if let Some(ledger) = ledger {
+            let mut ledger = ledger.lock().map_err(|_| eyre!("ledger lock poisoned"))?;
+            for generated in &batch {
+                ledger.record(
+                    &generated.transaction,
+                    &generated.routing_key,
+                    generated.expectation,
+                )?;
+            }
+        }
+        published.fetch_add(batch.len() as u64, Ordering::Relaxed);
+    }
+    sink.flush()?;
+
+    Ok(())
+}
+
+/// Returns the weighted identifier types that the payment provider has identifiers of.
+fn weighted_identifier_types<'a>(
+    identifiers: &IdentifierPool,
     payment_provider: &str,
-) -> Vec<&'a TokenRecord> {
-    payment_card_tokens
+    weights: &'a [(String, u32)],
+) -> Vec<&'a (String, u32)> {
+    let types = identifiers
+        .types(payment_provider)
+        .map(|identifier_type| identifier_type.to_string())
+        .collect::<Vec<_>>();
+    weights
         .iter()
-        .filter(|token| token.payment_slug == payment_provider)
+        .filter(|(identifier_type, weight)| *weight > 0 && types.contains(identifier_type))
         .collect()
 }
 
-//Provided with a set of retailer specific identifier records
-//select a subset of identifiers based on the payment provider
-fn select_identifiers_per_payment_provider<'a>(
-    identifiers: &'a [IdentifierRecord],
+/// Publishes a transaction, recording how long it took in the metrics.
+fn publish(sink: &mut dyn Sink, transaction: &Transaction, routing_key: &str) -> Result<()> {
+    let started = Instant::now();
+    sink.publish(transaction, routing_key).inspect_err(|_| {
+        metrics::record_error("publish");
+    })?;
+    metrics::record_published(transaction, routing_key, started.elapsed());
+    Ok(())
+}
+
+/// Chooses an identifier type by weight, from those the payment provider has identifiers of.
+fn select_identifier_type(
+    identifiers: &IdentifierPool,
     payment_provider: &str,
-) -> Vec<&'a IdentifierRecord> {
+    weights: &[(String, u32)],
+    rng: &mut impl Rng,
+) -> Result<IdentifierType> {
+    let (identifier_type, _) = *weighted_identifier_types(identifiers, payment_provider, weights)
+        .choose_weighted(rng, |(_, weight)| *weight)
+        .map_err(|e| eyre!("failed to select identifier type: {e}"))?;
+
     identifiers
-        .iter()
-        .filter(|identifier| identifier.payment_slug == payment_provider)
-        .collect()
+        .types(payment_provider)
+        .find(|candidate| candidate.to_string() == *identifier_type)
+        .ok_or_else(|| eyre!("unknown identifier type {identifier_type}"))
 }
 
 fn create_transaction(
-    config: &TransactorConfig,
+    retailer: &Retailer,
     payment_provider: &str,
-    payment_card_tokens: &[&TokenRecord],
-    identifiers: &[&IdentifierRecord],
+    token: &TokenRecord,
+    identifier: &IdentifierRecord,
+    rng: &mut impl Rng,
+    transaction_date: DateTime<Utc>,
 ) -> Result<Transaction> {
-    let token = payment_card_tokens
-        .choose(&mut rand::thread_rng())
-        .ok_or(eyre!("failed to select payment card token"))?;
-
-    let identifier = identifiers
-        .choose(&mut rand::thread_rng())
-        .ok_or(eyre!("failed to select identifier"))?;
+    let currency = select_currency(&retailer.config.currencies, rng)?;
 
     Ok(Transaction {
-        amount: rand::thread_rng().gen_range(config.amount_min..config.amount_max),
-        transaction_date: Utc::now(),
+        amount: retailer.amounts(payment_provider).sample(rng),
+        transaction_date,
         payment_provider: payment_provider.to_string(),
-        merchant_name: config.provider_slug.clone(),
-        transaction_id: Uuid::new_v4().to_string(),
-        auth_code: create_auth_code(),
+        merchant_name: retailer.config.provider_slug.clone(),
+        transaction_id: create_uuid(rng).to_string(),
+        auth_code: create_auth_code(rng),
         identifier: identifier.identifier.clone(),
         identifier_type: identifier.identifier_type.to_string(),
         token: token.token.clone(),
         first_six: token.first_six.clone(),
         last_four: token.last_four.clone(),
+        transaction_type: TransactionType::Purchase,
+        original_transaction_id: None,
+        currency,
+        location_id: identifier.location_id.clone(),
+        merchant_internal_id: identifier.merchant_internal_id.clone(),
+        store_identifiers: retailer.identifiers.store(identifier).cloned(),
     })
 }
 
-fn select_payment_provider(percentages: &[(String, i32); 3]) -> Result<String> {
+/// Picks a currency by weight, defaulting to GBP when none are configured.
+fn select_currency(currencies: &[(Currency, u32)], rng: &mut impl Rng) -> Result<Currency> {
+    if currencies.is_empty() {
+        return Ok(Currency::default());
+    }
+    let (currency, _) = currencies
+        .choose_weighted(rng, |(_, weight)| *weight)
+        .map_err(|e| eyre!("failed to select currency: {e}"))?;
+    Ok(*currency)
+}
+
+fn select_payment_provider(percentages: &[(String, u32)], rng: &mut impl Rng) -> Result<String> {
     let dist = WeightedIndex::new(percentages.iter().map(|item| item.1))?;
-    let mut rng = thread_rng();
-    let provider = percentages[dist.sample(&mut rng)].0.clone();
+    let provider = percentages[dist.sample(rng)].0.clone();
     Ok(provider)
 }
 
-fn create_auth_code() -> String {
-    let number = rand::thread_rng().gen_range(9..1_000_000);
+fn create_auth_code(rng: &mut impl Rng) -> String {
+    let number = rng.gen_range(9..1_000_000);
     format!("{number:0>6}")
 }
 
-fn queue_transaction(
-    exchange: &Exchange,
-    transaction: &Transaction,
-    routing_key: &String,
-) -> Result<()> {
-    // Publish a message to the "new_transaction" queue.
-    exchange.publish(Publish::new(&rmp_serde::to_vec(&transaction)?, routing_key))?;
-
-    Ok(())
+/// Creates a version 4 UUID from the generator, so seeded runs produce the same IDs.
+fn create_uuid(rng: &mut impl Rng) -> Uuid {
+    Builder::from_random_bytes(rng.gen()).into_uuid()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use atalanta::models::InjectionConfig;
     use pretty_assertions::assert_eq;
 
+    fn test_config() -> TransactorConfig {
+        TransactorConfig {
+            retailers: vec![RetailerConfig {
+                provider_slug: "test_slug".to_string(),
+                amount_min: Some(10),
+                amount_max: Some(100),
+                amounts: None,
+                provider_amounts: HashMap::new(),
+                percentage: vec![("visa".to_string(), 100)],
+                currencies: vec![],
+                identifier_type_percentage: None,
+                weight: None,
+                transactions_per_second: None,
+                refund_rate: 0.0,
+                partial_refund_rate: 0.0,
+                void_rate: 0.0,
+                settlement: None,
+                timestamps: None,
+            }],
+            transactions_per_second: Some(1),
+            phases: vec![],
+            publish_workers: 1,
+            max_in_flight: 1000,
+            total_transactions: None,
+            duration_secs: None,
+            seed: None,
+            start_date: None,
+            injection: InjectionConfig::default(),
+            ledger: None,
+            replay: None,
+            sink: SinkConfig::default(),
+            metrics: None,
+            control: None,
+        }
+    }
+
     #[test]
     fn create_auth_code_success() {
-        let auth_code = create_auth_code();
+        let auth_code = create_auth_code(&mut thread_rng());
         assert_eq!(auth_code.len(), 6);
     }
 
     #[test]
     fn create_transaction_success() -> Result<()> {
-        let transactor_config = TransactorConfig {
-            provider_slug: "test_slug".to_string(),
-            amount_min: 10,
-            amount_max: 100,
-            transactions_per_second: 1,
-            percentage: [
-                ("visa".to_string(), 100),
-                ("mastercard".to_string(), 0),
-                ("amex".to_string(), 0),
-            ],
-        };
+        let transactor_config = test_config();
 
-        let token_record = [&TokenRecord {
+        let token_record = TokenRecord {
             token: "test_token".to_string(),
             retailer_slug: "wasabi_club".to_string(),
             first_six: "666666".to_string(),
             last_four: "4444".to_string(),
             payment_slug: "visa".to_string(),
-        }];
+        };
 
-        let identifier_records = [&IdentifierRecord {
+        let identifier_record = IdentifierRecord {
             retailer_slug: "wasabi_club".to_string(),
             payment_slug: "visa".to_string(),
             identifier: "123456789".to_string(),
             identifier_type: IdentifierType::PrimaryMID,
-            _location_id: Some("loc_id_123456".to_string()),
-            _merchant_internal_id: Some("mi_1234567".to_string()),
-        }];
+            location_id: Some("loc_id_123456".to_string()),
+            merchant_internal_id: Some("mi_1234567".to_string()),
+        };
 
         let expected_transaction = Transaction {
             amount: 2000,
@@ -301,21 +884,178 @@ mod tests {
             merchant_name: "wasabi-club".to_string(),
             transaction_id: Uuid::new_v4().to_string(),
             auth_code: "123456".to_string(),
-            identifier: identifier_records[0].identifier.clone(),
-            identifier_type: identifier_records[0].identifier_type.to_string(),
-            token: token_record[0].token.clone(),
-            first_six: token_record[0].first_six.clone(),
-            last_four: token_record[0].last_four.clone(),
+            identifier: identifier_record.identifier.clone(),
+            identifier_type: identifier_record.identifier_type.to_string(),
+            token: token_record.token.clone(),
+            first_six: token_record.first_six.clone(),
+            last_four: token_record.last_four.clone(),
+            transaction_type: TransactionType::Purchase,
+            original_transaction_id: None,
+            currency: Currency::GBP,
+            location_id: None,
+            merchant_internal_id: None,
+            store_identifiers: None,
         };
 
+        let retailer = Retailer::new(
+            &transactor_config.retailers[0],
+            TokenPool::default(),
+            IdentifierPool::default(),
+        )?;
         let test_transaction = create_transaction(
-            &transactor_config,
+            &retailer,
             "visa",
             &token_record,
-            &identifier_records,
+            &identifier_record,
+            &mut thread_rng(),
+            Utc::now(),
         )?;
         assert_eq!(test_transaction.identifier, expected_transaction.identifier);
         assert_eq!(test_transaction.identifier_type, "PRIMARY");
+        assert_eq!(
+            test_transaction.location_id.as_deref(),
+            Some("loc_id_123456")
+        );
+        assert_eq!(
+            test_transaction.merchant_internal_id.as_deref(),
+            Some("mi_1234567")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn seeded_generation_is_reproducible() -> Result<()> {
+        let mut config = test_config();
+        config.seed = Some(42);
+        config.start_date = Some(Utc::now());
+
+        let retailer = Retailer::new(
+            &config.retailers[0],
+            ["token_1", "token_2", "token_3"]
+                .map(|token| TokenRecord {
+                    token: token.to_string(),
+                    retailer_slug: "test_slug".to_string(),
+                    first_six: "666666".to_string(),
+                    last_four: "4444".to_string(),
+                    payment_slug: "visa".to_string(),
+                })
+                .into_iter()
+                .collect(),
+            ["mid_1", "mid_2"]
+                .map(|identifier| IdentifierRecord {
+                    retailer_slug: "test_slug".to_string(),
+                    payment_slug: "visa".to_string(),
+                    identifier: identifier.to_string(),
+                    identifier_type: IdentifierType::PrimaryMID,
+                    location_id: None,
+                    merchant_internal_id: None,
+                })
+                .into_iter()
+                .collect(),
+        )?;
+
+        let generate = || -> Result<Vec<u8>> {
+            let profile = LoadProfile::from_config(&config)?;
+            let mut rng = create_rng(config.seed);
+            let mut clock = create_clock(&config);
+            let mut stream = Vec::new();
+            for _ in 0..50 {
+                let tx = generate_transaction(&retailer, &mut rng, clock.tick(&profile))?;
+                let routing_key = tx.routing_key();
+                stream.extend(rmp_serde::to_vec(&tx)?);
+                stream.extend(routing_key.into_bytes());
+            }
+            Ok(stream)
+        };
+
+        assert_eq!(generate()?, generate()?);
+
+        Ok(())
+    }
+
+    fn test_identifier(identifier: &str, identifier_type: IdentifierType) -> IdentifierRecord {
+        IdentifierRecord {
+            retailer_slug: "test_slug".to_string(),
+            payment_slug: "visa".to_string(),
+            identifier: identifier.to_string(),
+            identifier_type,
+            location_id: None,
+            merchant_internal_id: None,
+        }
+    }
+
+    #[test]
+    fn select_identifier_type_skips_unweighted_types() -> Result<()> {
+        let identifiers = [
+            test_identifier("mid_1", IdentifierType::PrimaryMID),
+            test_identifier("psimi_1", IdentifierType::Psimi),
+        ]
+        .into_iter()
+        .collect::<IdentifierPool>();
+        let weights = vec![
+            ("PRIMARY".to_string(), 0),
+            ("SECONDARY".to_string(), 1),
+            ("PSIMI".to_string(), 1),
+        ];
+
+        let selected = select_identifier_type(&identifiers, "visa", &weights, &mut thread_rng())?;
+
+        assert_eq!(selected, IdentifierType::Psimi);
+
+        Ok(())
+    }
+
+    #[test]
+    fn validate_rejects_scheme_without_tokens() {
+        let validate = |percentage: Vec<(String, u32)>| -> Result<()> {
+            let mut config = test_config();
+            config.retailers[0].percentage = percentage;
+            Retailer::new(
+                &config.retailers[0],
+                std::iter::once(TokenRecord {
+                    token: "token_1".to_string(),
+                    retailer_slug: "test_slug".to_string(),
+                    first_six: "666666".to_string(),
+                    last_four: "4444".to_string(),
+                    payment_slug: "visa".to_string(),
+                })
+                .collect(),
+                std::iter::once(test_identifier("mid_1", IdentifierType::PrimaryMID)).collect(),
+            )?
+            .validate()
+        };
+
+        assert!(validate(vec![("visa".to_string(), 50), ("amex".to_string(), 50)]).is_err());
+        assert!(validate(vec![("visa".to_string(), 50), ("amex".to_string(), 0)]).is_ok());
+    }
+
+    #[test]
+    fn set_percentage_keeps_percentages_that_cannot_be_served() -> Result<()> {
+        let config = test_config();
+        let mut retailer = Retailer::new(
+            &config.retailers[0],
+            std::iter::once(TokenRecord {
+                token: "token_1".to_string(),
+                retailer_slug: "test_slug".to_string(),
+                first_six: "666666".to_string(),
+                last_four: "4444".to_string(),
+                payment_slug: "visa".to_string(),
+            })
+            .collect(),
+            std::iter::once(test_identifier("mid_1", IdentifierType::PrimaryMID)).collect(),
+        )?;
+        let visa_only = vec![("visa".to_string(), 100)];
+
+        retailer.set_percentage(visa_only.clone())?;
+        assert!(retailer
+            .set_percentage(vec![("amex".to_string(), 100)])
+            .is_err());
+        assert_eq!(retailer.percentage, visa_only);
+
+        let check = retailer.percentage_check();
+        assert!(check(&visa_only).is_ok());
+        assert!(check(&[("amex".to_string(), 100)]).is_err());
 
         Ok(())
     }
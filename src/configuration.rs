@@ -1,4 +1,4 @@
-use crate::models::{DistributorConfig, Settings, TransactorConfig};
+use crate::models::{DistributorConfig, RetailerConfig, Settings, TransactorConfig};
 use color_eyre::{eyre::eyre, Result};
 use std::fs;
 use toml;
@@ -12,7 +12,7 @@ use tracing::info;
 pub fn load_transactor_config(settings: &Settings) -> Result<TransactorConfig> {
     info!(?settings.config_file_path, "reading transactor config");
     let contents = fs::read_to_string(&settings.config_file_path)?;
-    toml::from_str(&contents).map_err(|e| {
+    parse_transactor_config(&contents).map_err(|e| {
         eyre!(
             "failed to load transactor config from {}:\n{}",
             settings.config_file_path.to_string_lossy(),
@@ -21,6 +21,21 @@ pub fn load_transactor_config(settings: &Settings) -> Result<TransactorConfig> {
     })
 }
 
+fn parse_transactor_config(contents: &str) -> Result<TransactorConfig, toml::de::Error> {
+    let table: toml::Table = toml::from_str(contents)?;
+    let mut config: TransactorConfig = toml::Value::Table(table.clone()).try_into()?;
+
+    // Without a `retailers` list, the top-level fields describe a single retailer. Replays need
+    // no retailers at all.
+    if !table.contains_key("retailers") && !table.contains_key("replay") {
+        config
+            .retailers
+            .push(toml::Value::Table(table).try_into::<RetailerConfig>()?);
+    }
+
+    Ok(config)
+}
+
 /// Reads the distributor configuration from the file specified in `settings.config_file_path`.
 ///
 /// # Errors
@@ -49,3 +64,254 @@ pub fn load_settings() -> Result<Settings> {
 
     Ok(env_settings)
 }
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use std::time::Duration;
+
+    use super::*;
+    use crate::formatters::{self, Formatter as _};
+    use crate::models::{
+        AmountDistribution, ConsumerKind, FormatterKind, Pipeline, Transaction, TransactionType,
+    };
+
+    #[test]
+    fn single_retailer_config_loads_as_one_retailer() -> Result<()> {
+        let config = parse_transactor_config(include_str!("../configs/costa.toml"))?;
+
+        assert_eq!(config.retailers.len(), 1);
+        assert_eq!(config.retailers[0].provider_slug, "costa");
+        assert_eq!(config.transactions_per_second, Some(28));
+
+        Ok(())
+    }
+
+    #[test]
+    fn replay_config_needs_no_retailers() -> Result<()> {
+        let config = parse_transactor_config(
+            r#"
+            [replay]
+            path = "incident.jsonl"
+            speed = 2.0
+            "#,
+        )?;
+
+        assert!(config.retailers.is_empty());
+        assert_eq!(config.replay.map(|replay| replay.speed), Some(Some(2.0)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn multi_retailer_config_loads_each_retailer() -> Result<()> {
+        let config = parse_transactor_config(
+            r#"
+            [[retailers]]
+            provider_slug = "costa"
+            amount_min = 300
+            amount_max = 1000
+            percentage = [['visa', 100], ['mastercard', 0], ['amex', 0]]
+            transactions_per_second = 20
+
+            [[retailers]]
+            provider_slug = "wasabi-club"
+            amounts.LogNormal = { median = 850.0, sigma = 0.6, max = 20000 }
+            provider_amounts.amex.Fixed = [500, 1000]
+            percentage = [['visa', 60], ['amex', 40]]
+            identifier_type_percentage = [['PRIMARY', 90], ['PSIMI', 10]]
+            weight = 5.0
+            "#,
+        )?;
+
+        assert_eq!(config.transactions_per_second, None);
+        assert_eq!(
+            config.retailers[1].percentage,
+            vec![("visa".to_owned(), 60), ("amex".to_owned(), 40)]
+        );
+        assert!(matches!(
+            config.retailers[1].amount_distribution(),
+            Some(AmountDistribution::LogNormal { .. })
+        ));
+        assert!(matches!(
+            config.retailers[1].provider_amounts.get("amex"),
+            Some(AmountDistribution::Fixed(_))
+        ));
+        assert_eq!(
+            config
+                .retailers
+                .iter()
+                .map(RetailerConfig::weight)
+                .collect::<Vec<_>>(),
+            vec![20.0, 5.0]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn visa_distributors_each_send_one_half_of_a_lifecycle() -> Result<()> {
+        let auth: DistributorConfig = toml::from_str(include_str!("../configs/visa-auth.toml"))?;
+        let settlement: DistributorConfig =
+            toml::from_str(include_str!("../configs/visa-settlement.toml"))?;
+        let transaction = |transaction_type| Transaction {
+            merchant_name: "test_slug".to_owned(),
+            transaction_id: "tx_1".to_owned(),
+            identifier: "mid_1".to_owned(),
+            token: "token_1".to_owned(),
+            transaction_type,
+            ..Transaction::fixture()
+        };
+
+        assert!(auth.accepts(&transaction(TransactionType::Auth)));
+        assert!(!auth.accepts(&transaction(TransactionType::Settlement)));
+        assert!(settlement.accepts(&transaction(TransactionType::Settlement)));
+        assert!(!settlement.accepts(&transaction(TransactionType::Auth)));
+        assert!(settlement.accepts(&transaction(TransactionType::Purchase)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn distributor_pipeline_defaults_to_the_provider_slug() -> Result<()> {
+        let settlement: DistributorConfig =
+            toml::from_str(include_str!("../configs/visa-settlement.toml"))?;
+        let wasabi: DistributorConfig =
+            toml::from_str(include_str!("../configs/wasabi-club.toml"))?;
+
+        assert_eq!(
+            settlement.pipeline()?,
+            Pipeline {
+                consumer: ConsumerKind::Delay,
+                formatter: FormatterKind::VisaSettlement,
+                delay: Some(Duration::from_secs(10)),
+                transaction_types: vec![
+                    TransactionType::Purchase,
+                    TransactionType::Settlement,
+                    TransactionType::Refund,
+                    TransactionType::Void,
+                ],
+            }
+        );
+        assert_eq!(
+            wasabi.pipeline()?,
+            Pipeline {
+                consumer: ConsumerKind::Batch,
+                formatter: FormatterKind::Wasabi,
+                delay: None,
+                transaction_types: vec![
+                    TransactionType::Purchase,
+                    TransactionType::Settlement,
+                    TransactionType::Refund,
+                    TransactionType::Void,
+                ],
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn reversals_are_dropped_before_formatters_that_cannot_send_them() -> Result<()> {
+        type Format = fn(Vec<Transaction>) -> Result<String>;
+        let pipelines: [(&str, Format); 2] = [
+            ("iceland-bonus-card", formatters::iceland::Formatter::format),
+            ("amex-auth", formatters::amex_auth::Formatter::format),
+        ];
+        for (slug, format) in pipelines {
+            let config: DistributorConfig = toml::from_str(&format!(
+                r#"
+                provider_slug = "{slug}"
+                routing_key = "transactions.*.{slug}"
+                batch_size = 1
+
+                [sender.API]
+                url = "http://localhost:6502/mock/{slug}"
+                "#
+            ))?;
+            let pipeline = config.pipeline()?;
+            let config = DistributorConfig {
+                transaction_types: pipeline.transaction_types,
+                ..config
+            };
+            let refund = Transaction {
+                amount: -245,
+                transaction_id: "test_transaction_id_2".to_owned(),
+                transaction_type: TransactionType::Refund,
+                original_transaction_id: Some("test_transaction_id_1".to_owned()),
+                ..Transaction::fixture()
+            };
+
+            let sent = [Transaction::fixture(), refund]
+                .into_iter()
+                .filter(|transaction| config.accepts(transaction))
+                .collect::<Vec<_>>();
+            assert_eq!(sent.len(), 1);
+            assert!(format(sent).is_ok());
+
+            let reversals: DistributorConfig = toml::from_str(&format!(
+                r#"
+                provider_slug = "{slug}"
+                routing_key = "transactions.*.{slug}"
+                transaction_types = ["Purchase", "Refund"]
+                batch_size = 1
+
+                [sender.API]
+                url = "http://localhost:6502/mock/{slug}"
+                "#
+            ))?;
+            assert!(reversals.pipeline().is_err());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn distributor_pipeline_can_be_configured() -> Result<()> {
+        let parse = |pipeline: &str| -> Result<DistributorConfig> {
+            Ok(toml::from_str(&format!(
+                r#"
+                provider_slug = "amex-settlement"
+                routing_key = "transactions.amex.*"
+                batch_size = 1
+                {pipeline}
+
+                [sender.API]
+                url = "http://localhost:6502/mock/amex"
+                "#
+            ))?)
+        };
+
+        assert_eq!(
+            parse(
+                r#"
+                consumer = "Delay"
+                formatter = "AmexSettlement"
+                delay_secs = 30
+                "#
+            )?
+            .pipeline()?,
+            Pipeline {
+                consumer: ConsumerKind::Delay,
+                formatter: FormatterKind::AmexSettlement,
+                delay: Some(Duration::from_secs(30)),
+                transaction_types: vec![],
+            }
+        );
+        assert!(parse(r#"formatter = "AmexSettlement""#)?
+            .pipeline()
+            .is_err());
+        assert!(parse(
+            r#"
+            consumer = "Instant"
+            formatter = "AmexSettlement"
+            delay_secs = 30
+            "#
+        )?
+        .pipeline()
+        .is_err());
+
+        Ok(())
+    }
+}
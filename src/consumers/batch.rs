@@ -59,7 +59,7 @@ impl super::Consumer for Consumer {
                 queue.name()
             )
         })?;
-        for messages in consumer
+        let transactions = consumer
             .receiver()
             .iter()
             .take(message_count as usize)
@@ -70,16 +70,14 @@ impl super::Consumer for Consumer {
                     None
                 }
             })
-            .collect::<Vec<_>>()
-            .chunks(self.config.batch_size)
-        {
-            let transactions = messages
-                .iter()
-                .map(|message| rmp_serde::from_slice::<Transaction>(&message.body))
-                .collect::<Result<Vec<_>, rmp_serde::decode::Error>>()?;
-
+            .map(|message| rmp_serde::from_slice::<Transaction>(&message.body))
+            .collect::<Result<Vec<_>, rmp_serde::decode::Error>>()?
+            .into_iter()
+            .filter(|transaction| self.config.accepts(transaction))
+            .collect::<Vec<_>>();
+        for transactions in transactions.chunks(self.config.batch_size) {
             info!("sending batch of {} transactions.", transactions.len());
-            f(transactions)?;
+            f(transactions.to_vec())?;
         }
 
         debug!("finished consuming messages from queue {}.", queue.name());
@@ -11,6 +11,7 @@ use crate::{
 };
 
 /// A consumer that reads messages off a queue and sends them after a delay.
+///
 /// Useful for settlement providers that send transactions one at a time, usually some time after
 /// the corresponding auth transaction was sent.
 pub struct Consumer {
@@ -73,6 +74,11 @@ impl Consumer {
         F: Fn(Vec<Transaction>) -> Result<()>,
     {
         let tx: Transaction = rmp_serde::from_slice(&delivery.body)?;
+        if !self.config.accepts(&tx) {
+            trace!(transaction_type = %tx.transaction_type, "dropping transaction");
+            consumer.ack(delivery)?;
+            return Ok(());
+        }
         let now = Utc::now();
         let send_at = tx.transaction_date + self.delay;
         let delay = send_at - now;
@@ -1,6 +1,6 @@
 use amiquip::{Channel, ConsumerMessage, ConsumerOptions, QueueDeclareOptions};
 use color_eyre::Result;
-use tracing::info;
+use tracing::{info, trace};
 
 use crate::{
     consumers::queue_declare,
@@ -40,7 +40,11 @@ impl super::Consumer for Consumer {
             match message {
                 ConsumerMessage::Delivery(delivery) => {
                     let tx: Transaction = rmp_serde::from_slice(&delivery.body)?;
-                    f(vec![tx])?;
+                    if self.config.accepts(&tx) {
+                        f(vec![tx])?;
+                    } else {
+                        trace!(transaction_type = %tx.transaction_type, "dropping transaction");
+                    }
                     consumer.ack(delivery)?;
                 }
                 other => {
@@ -1,4 +1,4 @@
-use crate::{formatters::to_pounds, models::Transaction};
+use crate::models::Transaction;
 use chrono::FixedOffset;
 use color_eyre::{eyre::eyre, Result};
 use serde_json::json;
@@ -11,15 +11,16 @@ impl super::Formatter for Formatter {
             .into_iter()
             .next()
             .ok_or_else(|| eyre!("Expected at least one transaction."))?;
+        super::reject_reversal(&transaction, "an Amex auth")?;
 
-        let mst_timezone =
-            FixedOffset::west_opt(7 * 60 * 60).ok_or(eyre!("Failed to construct MST timezone"))?;
+        let mst_timezone = FixedOffset::west_opt(7 * 60 * 60)
+            .ok_or_else(|| eyre!("Failed to construct MST timezone"))?;
         let mst_datetime = transaction.transaction_date.with_timezone(&mst_timezone);
         let auth = json!({
             "transaction_id": transaction.transaction_id,
             "offer_id": transaction.transaction_id,
             "transaction_time": mst_datetime.to_string(),
-            "transaction_amount": to_pounds(transaction.amount),
+            "transaction_amount": transaction.money().to_string(),
             "cm_alias": transaction.token,
             "merchant_number": transaction.identifier,
             "approval_code": transaction.auth_code,
@@ -35,30 +36,16 @@ mod tests {
     use pretty_assertions::assert_eq;
 
     use crate::formatters::Formatter as _;
+    use crate::models::TransactionType;
 
     use super::*;
 
-    #[test]
-    fn to_pounds_success() {
-        let pounds = to_pounds(235);
-        assert_eq!(pounds, "2.35");
-    }
-
     #[test]
     fn amex_auth_valid() -> Result<()> {
         let dt = Utc::now();
         let test_transaction = Transaction {
-            amount: 245,
             transaction_date: dt,
-            payment_provider: "visa".to_owned(),
-            merchant_name: "test_merchant".to_owned(),
-            transaction_id: "test_transaction_id_1".to_owned(),
-            auth_code: "123456".to_owned(),
-            identifier: "12345678".to_owned(),
-            identifier_type: "PRIMARY".to_owned(),
-            token: "98765432123456789".to_owned(),
-            first_six: "123456".to_owned(),
-            last_four: "7890".to_owned(),
+            ..Transaction::fixture()
         };
 
         let json_result = Formatter::format(vec![test_transaction]);
@@ -81,4 +68,18 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn amex_auth_rejects_voids() {
+        let void = Transaction {
+            amount: -245,
+            payment_provider: "amex".to_owned(),
+            transaction_id: "test_transaction_id_2".to_owned(),
+            transaction_type: TransactionType::Void,
+            original_transaction_id: Some("test_transaction_id_1".to_owned()),
+            ..Transaction::fixture()
+        };
+
+        assert!(Formatter::format(vec![void]).is_err());
+    }
 }
@@ -1,8 +1,8 @@
-use crate::{formatters::to_pounds, models::Transaction};
+use crate::models::Transaction;
 use color_eyre::{eyre::eyre, Result};
 use serde_json::json;
 
-struct Formatter;
+pub struct Formatter;
 
 impl super::Formatter for Formatter {
     fn format(transactions: Vec<Transaction>) -> Result<String> {
@@ -11,21 +11,25 @@ impl super::Formatter for Formatter {
             .next()
             .ok_or_else(|| eyre!("Expected at least one transaction for Amex settlement."))?;
 
-        let settlement = json!(
+        let mut settlement = json!(
             {
                 "transactionId": transaction.transaction_id,
                 "offerId": transaction.transaction_id,
                 "transactionDate": transaction.transaction_date.to_string(),
-                "transactionAmount": to_pounds(transaction.amount),
+                "transactionAmount": transaction.money().to_string(),
                 "cardToken": transaction.token,
                 "merchantNumber": transaction.identifier,
                 "approvalCode": transaction.auth_code,
                 "dpan": "",
                 "partnerId": "AADP0050",
                 "recordId": "0224133845625011230183160001602891525AADP00400",
-                "currencyCode": "840"
+                "currencyCode": transaction.currency.numeric_string()
             }
         );
+        if transaction.is_reversal() {
+            settlement["transactionType"] = transaction.transaction_type.to_string().into();
+            settlement["originalTransactionId"] = transaction.original_transaction_id.into();
+        }
 
         Ok(settlement.to_string())
     }
@@ -37,30 +41,16 @@ mod tests {
     use pretty_assertions::assert_eq;
 
     use crate::formatters::Formatter as _;
+    use crate::models::TransactionType;
 
     use super::*;
 
-    #[test]
-    fn to_pounds_success() {
-        let pounds = to_pounds(235);
-        assert_eq!(pounds, "2.35");
-    }
-
     #[test]
     fn amex_settlement_valid() -> Result<()> {
         let dt = Utc::now();
         let test_transaction = Transaction {
-            amount: 245,
             transaction_date: dt,
-            payment_provider: "visa".to_owned(),
-            merchant_name: "test_merchant".to_owned(),
-            transaction_id: "test_transaction_id_1".to_owned(),
-            auth_code: "123456".to_owned(),
-            identifier: "12345678".to_owned(),
-            identifier_type: "PRIMARY".to_owned(),
-            token: "98765432123456789".to_owned(),
-            first_six: "123456".to_owned(),
-            last_four: "7890".to_owned(),
+            ..Transaction::fixture()
         };
 
         let json_result = Formatter::format(vec![test_transaction]);
@@ -75,7 +65,7 @@ mod tests {
             "dpan": "",
             "partnerId": "AADP0050",
             "recordId": "0224133845625011230183160001602891525AADP00400",
-            "currencyCode": "840"
+            "currencyCode": "826"
         });
 
         assert_eq!(
@@ -85,4 +75,23 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn amex_settlement_refund_is_marked() -> Result<()> {
+        let refund = Transaction {
+            amount: -245,
+            payment_provider: "amex".to_owned(),
+            transaction_id: "test_transaction_id_2".to_owned(),
+            transaction_type: TransactionType::Refund,
+            original_transaction_id: Some("test_transaction_id_1".to_owned()),
+            ..Transaction::fixture()
+        };
+
+        let json = serde_json::from_str::<serde_json::Value>(&Formatter::format(vec![refund])?)?;
+        assert_eq!(json["transactionAmount"], "-2.45");
+        assert_eq!(json["transactionType"], "refund");
+        assert_eq!(json["originalTransactionId"], "test_transaction_id_1");
+
+        Ok(())
+    }
 }
@@ -1,4 +1,4 @@
-use crate::{formatters::to_pounds, models::Transaction};
+use crate::models::Transaction;
 use color_eyre::Result;
 use serde_json::json;
 
@@ -11,20 +11,24 @@ impl super::Formatter for Formatter {
         let costa_transactions = transactions
             .into_iter()
             .map(|transaction| {
-                json!({
+                let retailer_location_id = transaction.retailer_location_id().to_owned();
+                let mut record = json!({
                     "transaction_id": transaction.transaction_id,
                     "payment_card_type": transaction.payment_provider,
                     "payment_card_first_six": transaction.first_six,
                     "payment_card_last_four": transaction.last_four,
-                    "amount": to_pounds(transaction.amount),
-                    "currency_code": "GBP",
+                    "amount": transaction.money().to_string(),
+                    "currency_code": transaction.currency.alpha_code(),
                     "auth_code": transaction.auth_code,
                     "date": transaction.transaction_date,
                     "merchant_identifier": transaction.identifier,
-                    "retailer_location_id": transaction.auth_code,
+                    "retailer_location_id": retailer_location_id,
+                    "merchant_internal_id": transaction.merchant_internal_id,
                     "metadata": metadata,
                     "items_ordered": include_str!("costa_order_items.json")
-                })
+                });
+                super::mark_reversal(&mut record, &transaction);
+                record
             })
             .collect::<Vec<_>>();
 
@@ -38,6 +42,7 @@ mod tests {
     use pretty_assertions::assert_eq;
 
     use crate::formatters::Formatter as _;
+    use crate::models::TransactionType;
 
     use super::*;
 
@@ -46,30 +51,19 @@ mod tests {
         let dt = Utc::now();
         let test_transactions = vec![
             Transaction {
-                amount: 245,
                 transaction_date: dt,
-                payment_provider: "visa".to_owned(),
-                merchant_name: "test_merchant".to_owned(),
-                transaction_id: "test_transaction_id_1".to_owned(),
-                auth_code: "123456".to_owned(),
-                identifier: "12345678".to_owned(),
-                identifier_type: "PRIMARY".to_owned(),
-                token: "98765432123456789".to_owned(),
-                first_six: "123456".to_owned(),
-                last_four: "7890".to_owned(),
+                location_id: Some("store_0001".to_owned()),
+                merchant_internal_id: Some("brand_0001".to_owned()),
+                ..Transaction::fixture()
             },
             Transaction {
                 amount: 735,
                 transaction_date: dt,
-                payment_provider: "visa".to_owned(),
                 merchant_name: "test_merchant2".to_owned(),
                 transaction_id: "test_transaction_id_2".to_owned(),
                 auth_code: "654321".to_owned(),
                 identifier: "87654321".to_owned(),
-                identifier_type: "PRIMARY".to_owned(),
-                token: "98765432123456789".to_owned(),
-                first_six: "123456".to_owned(),
-                last_four: "7890".to_owned(),
+                ..Transaction::fixture()
             },
         ];
 
@@ -83,12 +77,13 @@ mod tests {
                 "payment_card_type": "visa",
                 "payment_card_first_six": "123456",
                 "payment_card_last_four": "7890",
-                "amount": to_pounds(245),
+                "amount": "2.45",
                 "currency_code": "GBP",
                 "auth_code": "123456",
                 "date": dt,
                 "merchant_identifier": "12345678",
-                "retailer_location_id": "123456",
+                "retailer_location_id": "store_0001",
+                "merchant_internal_id": "brand_0001",
                 "metadata": metadata,
                 "items_ordered": include_str!("costa_order_items.json")
             },
@@ -97,12 +92,13 @@ mod tests {
                 "payment_card_type": "visa",
                 "payment_card_first_six": "123456",
                 "payment_card_last_four": "7890",
-                "amount": to_pounds(735),
+                "amount": "7.35",
                 "currency_code": "GBP",
                 "auth_code": "654321",
                 "date": dt,
                 "merchant_identifier": "87654321",
-                "retailer_location_id": "654321",
+                "retailer_location_id": "87654321",
+                "merchant_internal_id": null,
                 "metadata": metadata,
                 "items_ordered": include_str!("costa_order_items.json")
             }
@@ -115,4 +111,22 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn costa_refund_is_marked() -> Result<()> {
+        let refund = Transaction {
+            amount: -245,
+            transaction_id: "test_transaction_id_2".to_owned(),
+            transaction_type: TransactionType::Refund,
+            original_transaction_id: Some("test_transaction_id_1".to_owned()),
+            ..Transaction::fixture()
+        };
+
+        let json = serde_json::from_str::<serde_json::Value>(&Formatter::format(vec![refund])?)?;
+        assert_eq!(json[0]["amount"], "-2.45");
+        assert_eq!(json[0]["transaction_type"], "refund");
+        assert_eq!(json[0]["original_transaction_id"], "test_transaction_id_1");
+
+        Ok(())
+    }
 }
@@ -1,4 +1,4 @@
-use crate::{formatters::to_pounds, models::Transaction};
+use crate::models::Transaction;
 use chrono::prelude::*;
 use chrono_tz::Europe::London;
 use color_eyre::Result;
@@ -42,6 +42,8 @@ impl super::Formatter for Formatter {
         let mut wtr = WriterBuilder::new().from_writer(vec![]);
 
         for transaction in transactions {
+            super::reject_reversal(&transaction, "an Iceland file")?;
+            let amount = transaction.money().to_string();
             let iceland_tx = TransactionRecord {
                 first_six: transaction.first_six,
                 last_four: transaction.last_four,
@@ -50,10 +52,10 @@ impl super::Formatter for Formatter {
                 card_scheme_name: card_type_name(transaction.payment_provider.as_str()),
                 identifier: transaction.identifier.clone(),
                 transaction_date: date_to_timezone(&transaction.transaction_date),
-                amount: to_pounds(transaction.amount),
-                amount_unit: "GBP".to_owned(),
+                amount,
+                amount_unit: transaction.currency.alpha_code().to_owned(),
                 cashback_value: ".00".to_owned(),
-                cashback_unit: "GBP".to_owned(),
+                cashback_unit: transaction.currency.alpha_code().to_owned(),
                 transaction_id: transaction.transaction_id,
                 auth_code: transaction.auth_code.clone(),
             };
@@ -77,12 +79,13 @@ fn card_type_name(payment_provider: &str) -> String {
 
 fn date_to_timezone(date: &DateTime<Utc>) -> String {
     let tz_date = date.with_timezone(&London);
-    return tz_date.format("%Y-%m-%d %H:%M:%S").to_string();
+    tz_date.format("%Y-%m-%d %H:%M:%S").to_string()
 }
 
 #[cfg(test)]
 mod tests {
     use crate::formatters::Formatter as _;
+    use crate::models::TransactionType;
 
     use super::*;
     use chrono::{Duration, Utc};
@@ -96,28 +99,22 @@ mod tests {
             Transaction {
                 amount: 260,
                 transaction_date: dt,
-                payment_provider: "visa".to_owned(),
                 merchant_name: "Bink toffee".to_owned(),
                 transaction_id: "1234567890987654321234567".to_owned(),
                 auth_code: "098765".to_owned(),
                 identifier: "1111111111".to_owned(),
-                identifier_type: "PRIMARY".to_owned(),
                 token: "a_token_001".to_owned(),
-                first_six: "123456".to_owned(),
-                last_four: "7890".to_owned(),
+                ..Transaction::fixture()
             },
             Transaction {
                 amount: 4267,
                 transaction_date: dt,
-                payment_provider: "visa".to_owned(),
                 merchant_name: "Bink toffee".to_owned(),
                 transaction_id: "12345678909887654".to_owned(),
                 auth_code: "023454".to_owned(),
                 identifier: "1111111112".to_owned(),
-                identifier_type: "PRIMARY".to_owned(),
                 token: "a_token_002".to_owned(),
-                first_six: "123456".to_owned(),
-                last_four: "7890".to_owned(),
+                ..Transaction::fixture()
             },
         ];
 
@@ -144,4 +141,18 @@ mod tests {
         assert_eq!("2016-10-29 13:00:00", date_to_timezone(&datetime_utc));
         assert_eq!("2016-10-30 12:00:00", date_to_timezone(&day_later));
     }
+
+    #[test]
+    fn iceland_rejects_refunds() {
+        let refund = Transaction {
+            amount: -245,
+            merchant_name: "Bink toffee".to_owned(),
+            transaction_id: "test_transaction_id_2".to_owned(),
+            transaction_type: TransactionType::Refund,
+            original_transaction_id: Some("test_transaction_id_1".to_owned()),
+            ..Transaction::fixture()
+        };
+
+        assert!(Formatter::format(vec![refund]).is_err());
+    }
 }
@@ -1,6 +1,5 @@
 use crate::models::Transaction;
-use color_eyre::Result;
-use rust_decimal::prelude::*;
+use color_eyre::{eyre::eyre, Result};
 
 pub mod amex_auth;
 pub mod amex_settlement;
@@ -12,11 +11,6 @@ pub mod visa_auth;
 pub mod visa_settlement;
 pub mod wasabi;
 
-#[must_use]
-pub fn to_pounds(amount: i64) -> String {
-    Decimal::new(amount, 2).to_string()
-}
-
 pub trait Formatter {
     /// Formats a list of transactions into a string.
     ///
@@ -25,3 +19,25 @@ pub trait Formatter {
     /// Returns an error if the transactions cannot be formatted.
     fn format(transactions: Vec<Transaction>) -> Result<String>;
 }
+
+/// Adds a refund or void's type and the purchase it reverses to a retailer's JSON transaction, so
+/// that it cannot be mistaken for a purchase with a negative amount. Purchases are left as they
+/// are.
+fn mark_reversal(record: &mut serde_json::Value, transaction: &Transaction) {
+    if transaction.is_reversal() {
+        record["transaction_type"] = transaction.transaction_type.to_string().into();
+        record["original_transaction_id"] = transaction.original_transaction_id.clone().into();
+    }
+}
+
+/// Rejects a refund or void, for formats that have no way to tell one from a purchase.
+fn reject_reversal(transaction: &Transaction, format: &str) -> Result<()> {
+    if transaction.is_reversal() {
+        return Err(eyre!(
+            "{format} cannot carry a {}: {}",
+            transaction.transaction_type,
+            transaction.transaction_id
+        ));
+    }
+    Ok(())
+}
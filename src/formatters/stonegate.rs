@@ -1,4 +1,4 @@
-use crate::{formatters::to_pounds, models::Transaction};
+use crate::models::Transaction;
 use color_eyre::Result;
 use serde_json::json;
 
@@ -11,20 +11,24 @@ impl super::Formatter for Formatter {
         let stonegate_transactions = transactions
             .into_iter()
             .map(|transaction| {
-                json!({
+                let retailer_location_id = transaction.retailer_location_id().to_owned();
+                let mut record = json!({
                     "transaction_id": transaction.transaction_id,
                     "payment_card_type": card_type_name(transaction.payment_provider.as_str()),
                     "payment_card_first_six": transaction.first_six,
                     "payment_card_last_four": transaction.last_four,
-                    "amount": to_pounds(transaction.amount),
-                    "currency_code": "GBP",
+                    "amount": transaction.money().to_string(),
+                    "currency_code": transaction.currency.alpha_code(),
                     "auth_code": transaction.auth_code,
                     "date": transaction.transaction_date.to_rfc3339(),
                     "merchant_identifier": transaction.identifier,
-                    "retailer_location_id": transaction.identifier,
+                    "retailer_location_id": retailer_location_id,
+                    "merchant_internal_id": transaction.merchant_internal_id,
                     "metadata": metadata,
                     "items_ordered": include_str!("stonegate_order_items.json")
-                })
+                });
+                super::mark_reversal(&mut record, &transaction);
+                record
             })
             .collect::<Vec<_>>();
 
@@ -55,30 +59,19 @@ mod tests {
         let dt = Utc::now();
         let test_transactions = vec![
             Transaction {
-                amount: 245,
                 transaction_date: dt,
-                payment_provider: "visa".to_owned(),
-                merchant_name: "test_merchant".to_owned(),
-                transaction_id: "test_transaction_id_1".to_owned(),
-                auth_code: "123456".to_owned(),
-                identifier: "12345678".to_owned(),
-                identifier_type: "PRIMARY".to_owned(),
-                token: "98765432123456789".to_owned(),
-                first_six: "123456".to_owned(),
-                last_four: "7890".to_owned(),
+                location_id: Some("store_0001".to_owned()),
+                merchant_internal_id: Some("brand_0001".to_owned()),
+                ..Transaction::fixture()
             },
             Transaction {
                 amount: 735,
                 transaction_date: dt,
-                payment_provider: "visa".to_owned(),
                 merchant_name: "test_merchant2".to_owned(),
                 transaction_id: "test_transaction_id_2".to_owned(),
                 auth_code: "654321".to_owned(),
                 identifier: "87654321".to_owned(),
-                identifier_type: "PRIMARY".to_owned(),
-                token: "98765432123456789".to_owned(),
-                first_six: "123456".to_owned(),
-                last_four: "7890".to_owned(),
+                ..Transaction::fixture()
             },
         ];
 
@@ -92,12 +85,13 @@ mod tests {
                 "payment_card_type": "VISA DEBIT",
                 "payment_card_first_six": "123456",
                 "payment_card_last_four": "7890",
-                "amount": to_pounds(245),
+                "amount": "2.45",
                 "currency_code": "GBP",
                 "auth_code": "123456",
                 "date": dt.to_rfc3339(),
                 "merchant_identifier": "12345678",
-                "retailer_location_id": "12345678",
+                "retailer_location_id": "store_0001",
+                "merchant_internal_id": "brand_0001",
                 "metadata": metadata,
                 "items_ordered": include_str!("stonegate_order_items.json")
             },
@@ -106,12 +100,13 @@ mod tests {
                 "payment_card_type": "VISA DEBIT",
                 "payment_card_first_six": "123456",
                 "payment_card_last_four": "7890",
-                "amount": to_pounds(735),
+                "amount": "7.35",
                 "currency_code": "GBP",
                 "auth_code": "654321",
                 "date": dt.to_rfc3339(),
                 "merchant_identifier": "87654321",
                 "retailer_location_id": "87654321",
+                "merchant_internal_id": null,
                 "metadata": metadata,
                 "items_ordered": include_str!("stonegate_order_items.json")
             }
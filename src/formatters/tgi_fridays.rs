@@ -1,12 +1,15 @@
-use crate::{formatters::to_pounds, models::Transaction};
+use crate::models::{Money, Transaction};
 use color_eyre::Result;
 use serde_json::json;
 
 pub struct Formatter;
 
-fn gratuitise(amount: i64) -> (i64, i64) {
-    let gratuity = (amount as f64 * 0.1).round() as i64;
-    (amount - gratuity, gratuity)
+fn gratuitise(money: Money) -> (Money, Money) {
+    let gratuity = (money.amount as f64 * 0.1).round() as i64;
+    (
+        Money::new(money.amount - gratuity, money.currency),
+        Money::new(gratuity, money.currency),
+    )
 }
 
 impl super::Formatter for Formatter {
@@ -14,20 +17,24 @@ impl super::Formatter for Formatter {
         let tgi_fridays_transactions = transactions
             .into_iter()
             .map(|transaction| {
-                let (amount, gratuity) = gratuitise(transaction.amount);
-                json!({
+                let (amount, gratuity) = gratuitise(transaction.money());
+                let retailer_location_id = transaction.retailer_location_id().to_owned();
+                let mut record = json!({
                     "transaction_id": transaction.transaction_id,
                     "payment_card_type": card_type_name(transaction.payment_provider.as_str()),
                     "payment_card_first_six": transaction.first_six,
                     "payment_card_last_four": transaction.last_four,
-                    "amount": to_pounds(amount),
-                    "gratuity_amount": to_pounds(gratuity),
-                    "currency_code": "GBP",
+                    "amount": amount.to_string(),
+                    "gratuity_amount": gratuity.to_string(),
+                    "currency_code": transaction.currency.alpha_code(),
                     "auth_code": transaction.auth_code,
                     "date": transaction.transaction_date.to_rfc3339(),
                     "merchant_identifier": transaction.identifier,
-                    "retailer_location_id": transaction.identifier,
-                })
+                    "retailer_location_id": retailer_location_id,
+                    "merchant_internal_id": transaction.merchant_internal_id,
+                });
+                super::mark_reversal(&mut record, &transaction);
+                record
             })
             .collect::<Vec<_>>();
 
@@ -58,30 +65,19 @@ mod tests {
         let dt = Utc::now();
         let test_transactions = vec![
             Transaction {
-                amount: 245,
                 transaction_date: dt,
-                payment_provider: "visa".to_owned(),
-                merchant_name: "test_merchant".to_owned(),
-                transaction_id: "test_transaction_id_1".to_owned(),
-                auth_code: "123456".to_owned(),
-                identifier: "12345678".to_owned(),
-                identifier_type: "PRIMARY".to_owned(),
-                token: "98765432123456789".to_owned(),
-                first_six: "123456".to_owned(),
-                last_four: "7890".to_owned(),
+                location_id: Some("store_0001".to_owned()),
+                merchant_internal_id: Some("brand_0001".to_owned()),
+                ..Transaction::fixture()
             },
             Transaction {
                 amount: 735,
                 transaction_date: dt,
-                payment_provider: "visa".to_owned(),
                 merchant_name: "test_merchant2".to_owned(),
                 transaction_id: "test_transaction_id_2".to_owned(),
                 auth_code: "654321".to_owned(),
                 identifier: "87654321".to_owned(),
-                identifier_type: "PRIMARY".to_owned(),
-                token: "98765432123456789".to_owned(),
-                first_six: "123456".to_owned(),
-                last_four: "7890".to_owned(),
+                ..Transaction::fixture()
             },
         ];
 
@@ -92,26 +88,28 @@ mod tests {
                 "payment_card_type": "VISA DEBIT",
                 "payment_card_first_six": "123456",
                 "payment_card_last_four": "7890",
-                "amount": to_pounds(220),
-                "gratuity_amount": to_pounds(25),
+                "amount": "2.20",
+                "gratuity_amount": "0.25",
                 "currency_code": "GBP",
                 "auth_code": "123456",
                 "date": dt.to_rfc3339(),
                 "merchant_identifier": "12345678",
-                "retailer_location_id": "12345678",
+                "retailer_location_id": "store_0001",
+                "merchant_internal_id": "brand_0001",
             },
             {
                 "transaction_id": "test_transaction_id_2",
                 "payment_card_type": "VISA DEBIT",
                 "payment_card_first_six": "123456",
                 "payment_card_last_four": "7890",
-                "amount": to_pounds(661),
-                "gratuity_amount": to_pounds(74),
+                "amount": "6.61",
+                "gratuity_amount": "0.74",
                 "currency_code": "GBP",
                 "auth_code": "654321",
                 "date": dt.to_rfc3339(),
                 "merchant_identifier": "87654321",
                 "retailer_location_id": "87654321",
+                "merchant_internal_id": null,
             }
         ]);
 
@@ -1,4 +1,4 @@
-use crate::{formatters::to_pounds, models::Transaction};
+use crate::models::{Transaction, TransactionType};
 use color_eyre::{eyre::eyre, Result};
 use serde_json::json;
 
@@ -11,7 +11,10 @@ impl super::Formatter for Formatter {
             .next()
             .ok_or_else(|| eyre!("Expected at least one transaction."))?;
 
-        let amount = to_pounds(transaction.amount);
+        let transaction_type = transaction_type(&transaction)?;
+        // Visa sends reversals as a positive amount with a RETURN or REVERSAL transaction type.
+        let amount = transaction.money().abs().to_string();
+        let currency = transaction.currency;
         let date = transaction.transaction_date.to_rfc3339();
         let auth = json!({
             "CardId": transaction.transaction_id[0..9],
@@ -33,8 +36,8 @@ impl super::Formatter for Formatter {
                 {"Key": "Transaction.SettlementBillingAmount", "Value": 0},
                 {"Key": "Transaction.SettlementBillingCurrency", "Value": ""},
                 {"Key": "Transaction.SettlementUSDAmount", "Value": 0},
-                {"Key": "Transaction.CurrencyCodeNumeric", "Value": "840"},
-                {"Key": "Transaction.BillingCurrencyCode", "Value": "840"},
+                {"Key": "Transaction.CurrencyCodeNumeric", "Value": currency.numeric_string()},
+                {"Key": "Transaction.BillingCurrencyCode", "Value": currency.numeric_string()},
                 {"Key": "Transaction.USDAmount", "Value": amount},
                 {"Key": "Transaction.MerchantLocalPurchaseDate", "Value": "2019-12-19"},
                 {"Key": "Transaction.MerchantGroup.0.Name", "Value": "TEST_MG"},
@@ -45,7 +48,7 @@ impl super::Formatter for Formatter {
             ],
             "MessageId": "12345678",
             "MessageName": "AuthMessageTest",
-            "UserDefinedFieldsCollection": [{"Key": "TransactionType", "Value": "AUTH"}],
+            "UserDefinedFieldsCollection": [{"Key": "TransactionType", "Value": transaction_type}],
             "UserProfileId": "f292f99d-babf-528a-8d8a-19fa5f14f4"
         });
 
@@ -53,25 +56,38 @@ impl super::Formatter for Formatter {
     }
 }
 
-fn primary_identifier(transaction: &Transaction) -> String {
-    match transaction.identifier_type.as_str() {
-        "PRIMARY" => transaction.identifier.clone(),
-        _ => "PRIM11111".to_owned(),
+fn transaction_type(transaction: &Transaction) -> Result<&'static str> {
+    match transaction.transaction_type {
+        TransactionType::Purchase | TransactionType::Auth => Ok("AUTH"),
+        TransactionType::Refund => Ok("RETURN"),
+        TransactionType::Void => Ok("REVERSAL"),
+        TransactionType::Settlement => Err(eyre!(
+            "a Visa auth cannot carry a {}: {}",
+            transaction.transaction_type,
+            transaction.transaction_id
+        )),
     }
 }
 
+fn primary_identifier(transaction: &Transaction) -> String {
+    transaction
+        .identifier_of_type("PRIMARY")
+        .unwrap_or("PRIM11111")
+        .to_owned()
+}
+
 fn secondary_identifier(transaction: &Transaction) -> String {
-    match transaction.identifier_type.as_str() {
-        "SECONDARY" => transaction.identifier.clone(),
-        _ => "SEC222222".to_owned(),
-    }
+    transaction
+        .identifier_of_type("SECONDARY")
+        .unwrap_or("SEC222222")
+        .to_owned()
 }
 
 fn psimi_identifier(transaction: &Transaction) -> String {
-    match transaction.identifier_type.as_str() {
-        "PSIMI" => transaction.identifier.clone(),
-        _ => "PSIMI3333".to_owned(),
-    }
+    transaction
+        .identifier_of_type("PSIMI")
+        .unwrap_or("PSIMI3333")
+        .to_owned()
 }
 #[cfg(test)]
 mod tests {
@@ -81,28 +97,15 @@ mod tests {
     use crate::formatters::Formatter as _;
 
     use super::*;
-
-    #[test]
-    fn to_pounds_success() {
-        let pounds = to_pounds(235);
-        assert_eq!("2.35", pounds);
-    }
+    use crate::models::{Currency, StoreIdentifiers};
 
     #[test]
     fn visa_auth_valid() -> Result<()> {
         let dt = Utc::now();
         let test_transaction = Transaction {
-            amount: 245,
             transaction_date: dt,
-            payment_provider: "visa".to_owned(),
             merchant_name: "Bink Shop".to_owned(),
-            transaction_id: "test_transaction_id_1".to_owned(),
-            auth_code: "123456".to_owned(),
-            identifier: "12345678".to_owned(),
-            identifier_type: "PRIMARY".to_owned(),
-            token: "98765432123456789".to_owned(),
-            first_six: "123456".to_owned(),
-            last_four: "7890".to_owned(),
+            ..Transaction::fixture()
         };
 
         let json_result = Formatter::format(vec![test_transaction]);
@@ -128,8 +131,8 @@ mod tests {
                     {"Key": "Transaction.SettlementBillingAmount", "Value": 0},
                     {"Key": "Transaction.SettlementBillingCurrency", "Value": ""},
                     {"Key": "Transaction.SettlementUSDAmount", "Value": 0},
-                    {"Key": "Transaction.CurrencyCodeNumeric", "Value": "840"},
-                    {"Key": "Transaction.BillingCurrencyCode", "Value": "840"},
+                    {"Key": "Transaction.CurrencyCodeNumeric", "Value": "826"},
+                    {"Key": "Transaction.BillingCurrencyCode", "Value": "826"},
                     {"Key": "Transaction.USDAmount", "Value": "2.45"},
                     {"Key": "Transaction.MerchantLocalPurchaseDate", "Value": "2019-12-19"},
                     {"Key": "Transaction.MerchantGroup.0.Name", "Value": "TEST_MG"},
@@ -147,4 +150,79 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn visa_auth_uses_transaction_currency() -> Result<()> {
+        let test_transaction = Transaction {
+            merchant_name: "Bink Shop".to_owned(),
+            currency: Currency::JPY,
+            ..Transaction::fixture()
+        };
+
+        let json =
+            serde_json::from_str::<serde_json::Value>(&Formatter::format(vec![test_transaction])?)?;
+        let elements = json["MessageElementsCollection"]
+            .as_array()
+            .ok_or_else(|| eyre!("missing message elements"))?;
+        let value = |key: &str| {
+            elements
+                .iter()
+                .find(|element| element["Key"] == key)
+                .map(|element| element["Value"].clone())
+        };
+
+        assert_eq!(value("Transaction.BillingAmount"), Some(json!("245")));
+        assert_eq!(value("Transaction.CurrencyCodeNumeric"), Some(json!("392")));
+        assert_eq!(value("Transaction.BillingCurrencyCode"), Some(json!("392")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn visa_auth_sends_the_store_identifiers() -> Result<()> {
+        let test_transaction = Transaction {
+            merchant_name: "Bink Shop".to_owned(),
+            identifier: "store_id_1".to_owned(),
+            identifier_type: "SECONDARY".to_owned(),
+            location_id: Some("store_1".to_owned()),
+            store_identifiers: Some(StoreIdentifiers {
+                primary: Some("mid_1".to_owned()),
+                secondary: Some("store_id_1".to_owned()),
+                psimi: Some("psimi_1".to_owned()),
+            }),
+            ..Transaction::fixture()
+        };
+
+        let json =
+            serde_json::from_str::<serde_json::Value>(&Formatter::format(vec![test_transaction])?)?;
+        let elements = json["MessageElementsCollection"]
+            .as_array()
+            .ok_or_else(|| eyre!("missing message elements"))?;
+        let value = |key: &str| {
+            elements
+                .iter()
+                .find(|element| element["Key"] == key)
+                .map(|element| element["Value"].clone())
+        };
+
+        assert_eq!(
+            value("Transaction.MerchantCardAcceptorId"),
+            Some(json!("mid_1"))
+        );
+        assert_eq!(value("Transaction.VisaStoreId"), Some(json!("store_id_1")));
+        assert_eq!(value("Transaction.VisaMerchantId"), Some(json!("psimi_1")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn visa_auth_rejects_settlements() {
+        let settlement = Transaction {
+            merchant_name: "Bink Shop".to_owned(),
+            transaction_type: TransactionType::Settlement,
+            ..Transaction::fixture()
+        };
+
+        assert!(Formatter::format(vec![settlement]).is_err());
+    }
 }
@@ -1,4 +1,4 @@
-use crate::{formatters::to_pounds, models::Transaction};
+use crate::models::{Transaction, TransactionType};
 use color_eyre::{eyre::eyre, Result};
 use serde_json::json;
 
@@ -11,7 +11,10 @@ impl super::Formatter for Formatter {
             .next()
             .ok_or_else(|| eyre!("Expected at least one transaction."))?;
 
-        let amount = to_pounds(transaction.amount);
+        let transaction_type = transaction_type(&transaction)?;
+        // Visa sends reversals as a positive amount with a RETURN or REVERSAL transaction type.
+        let amount = transaction.money().abs().to_string();
+        let currency = transaction.currency;
         let date = transaction.transaction_date.to_rfc3339();
         let settlement = json!(
             {
@@ -30,12 +33,12 @@ impl super::Formatter for Formatter {
                     {"Key": "Transaction.VisaStoreId", "Value": secondary_identifier(&transaction)},
                     {"Key": "Transaction.SettlementDate", "Value": date},
                     {"Key": "Transaction.SettlementAmount", "Value": amount},
-                    {"Key": "Transaction.SettlementCurrencyCodeNumeric", "Value": 826},
+                    {"Key": "Transaction.SettlementCurrencyCodeNumeric", "Value": currency.numeric_code()},
                     {"Key": "Transaction.SettlementBillingAmount", "Value": amount},
-                    {"Key": "Transaction.SettlementBillingCurrency", "Value": "GBP"},
+                    {"Key": "Transaction.SettlementBillingCurrency", "Value": currency.alpha_code()},
                     {"Key": "Transaction.SettlementUSDAmount", "Value": amount},
-                    {"Key": "Transaction.CurrencyCodeNumeric", "Value": "840"},
-                    {"Key": "Transaction.BillingCurrencyCode", "Value": "840"},
+                    {"Key": "Transaction.CurrencyCodeNumeric", "Value": currency.numeric_string()},
+                    {"Key": "Transaction.BillingCurrencyCode", "Value": currency.numeric_string()},
                     {"Key": "Transaction.USDAmount", "Value": amount},
                     {"Key": "Transaction.MerchantLocalPurchaseDate", "Value": "2019-12-19"},
                     {"Key": "Transaction.MerchantGroup.0.Name", "Value": "TEST_MG"},
@@ -46,7 +49,7 @@ impl super::Formatter for Formatter {
                 ],
                 "MessageId": "12345678",
                 "MessageName": "SettlementMessageTest",
-                "UserDefinedFieldsCollection": [{"Key": "TransactionType", "Value": "SETTLE"}],
+                "UserDefinedFieldsCollection": [{"Key": "TransactionType", "Value": transaction_type}],
                 "UserProfileId": "f292f99d-babf-528a-8d8a-19fa5f14f4",
             }
         );
@@ -55,25 +58,38 @@ impl super::Formatter for Formatter {
     }
 }
 
-fn primary_identifier(transaction: &Transaction) -> String {
-    match transaction.identifier_type.as_str() {
-        "PRIMARY" => transaction.identifier.clone(),
-        _ => "PRIM11111".to_owned(),
+fn transaction_type(transaction: &Transaction) -> Result<&'static str> {
+    match transaction.transaction_type {
+        TransactionType::Purchase | TransactionType::Settlement => Ok("SETTLE"),
+        TransactionType::Refund => Ok("RETURN"),
+        TransactionType::Void => Ok("REVERSAL"),
+        TransactionType::Auth => Err(eyre!(
+            "a Visa settlement cannot carry a {}: {}",
+            transaction.transaction_type,
+            transaction.transaction_id
+        )),
     }
 }
 
+fn primary_identifier(transaction: &Transaction) -> String {
+    transaction
+        .identifier_of_type("PRIMARY")
+        .unwrap_or("PRIM11111")
+        .to_owned()
+}
+
 fn secondary_identifier(transaction: &Transaction) -> String {
-    match transaction.identifier_type.as_str() {
-        "SECONDARY" => transaction.identifier.clone(),
-        _ => "SEC222222".to_owned(),
-    }
+    transaction
+        .identifier_of_type("SECONDARY")
+        .unwrap_or("SEC222222")
+        .to_owned()
 }
 
 fn psimi_identifier(transaction: &Transaction) -> String {
-    match transaction.identifier_type.as_str() {
-        "PSIMI" => transaction.identifier.clone(),
-        _ => "PSIMI3333".to_owned(),
-    }
+    transaction
+        .identifier_of_type("PSIMI")
+        .unwrap_or("PSIMI3333")
+        .to_owned()
 }
 #[cfg(test)]
 mod tests {
@@ -84,27 +100,13 @@ mod tests {
 
     use super::*;
 
-    #[test]
-    fn to_pounds_success() {
-        let pounds = to_pounds(235);
-        assert_eq!("2.35", pounds);
-    }
-
     #[test]
     fn visa_settlement_valid() -> Result<()> {
         let dt = Utc::now();
         let test_transaction = Transaction {
-            amount: 245,
             transaction_date: dt,
-            payment_provider: "visa".to_owned(),
             merchant_name: "Bink Shop".to_owned(),
-            transaction_id: "test_transaction_id_1".to_owned(),
-            auth_code: "123456".to_owned(),
-            identifier: "12345678".to_owned(),
-            identifier_type: "PRIMARY".to_owned(),
-            token: "98765432123456789".to_owned(),
-            first_six: "123456".to_owned(),
-            last_four: "7890".to_owned(),
+            ..Transaction::fixture()
         };
 
         let json_result = Formatter::format(vec![test_transaction]);
@@ -130,8 +132,8 @@ mod tests {
                     {"Key": "Transaction.SettlementBillingAmount", "Value": "2.45"},
                     {"Key": "Transaction.SettlementBillingCurrency", "Value": "GBP"},
                     {"Key": "Transaction.SettlementUSDAmount", "Value": "2.45"},
-                    {"Key": "Transaction.CurrencyCodeNumeric", "Value": "840"},
-                    {"Key": "Transaction.BillingCurrencyCode", "Value": "840"},
+                    {"Key": "Transaction.CurrencyCodeNumeric", "Value": "826"},
+                    {"Key": "Transaction.BillingCurrencyCode", "Value": "826"},
                     {"Key": "Transaction.USDAmount", "Value": "2.45"},
                     {"Key": "Transaction.MerchantLocalPurchaseDate", "Value": "2019-12-19"},
                     {"Key": "Transaction.MerchantGroup.0.Name", "Value": "TEST_MG"},
@@ -149,4 +151,39 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn visa_refund_is_a_positive_return() -> Result<()> {
+        let refund = Transaction {
+            amount: -245,
+            merchant_name: "Bink Shop".to_owned(),
+            transaction_id: "test_transaction_id_2".to_owned(),
+            transaction_type: TransactionType::Refund,
+            original_transaction_id: Some("test_transaction_id_1".to_owned()),
+            ..Transaction::fixture()
+        };
+
+        let json = serde_json::from_str::<serde_json::Value>(&Formatter::format(vec![refund])?)?;
+        assert_eq!(
+            json["UserDefinedFieldsCollection"],
+            json!([{"Key": "TransactionType", "Value": "RETURN"}])
+        );
+        assert_eq!(
+            json["MessageElementsCollection"][0],
+            json!({"Key": "Transaction.BillingAmount", "Value": "2.45"})
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn visa_settlement_rejects_auths() {
+        let auth = Transaction {
+            merchant_name: "Bink Shop".to_owned(),
+            transaction_type: TransactionType::Auth,
+            ..Transaction::fixture()
+        };
+
+        assert!(Formatter::format(vec![auth]).is_err());
+    }
 }
@@ -1,4 +1,4 @@
-use crate::{formatters::to_pounds, models::Transaction};
+use crate::models::{Transaction, TransactionType};
 use color_eyre::Result;
 use csv::Writer;
 use rand::Rng;
@@ -36,17 +36,23 @@ pub struct TransactionRecord {
 
 pub struct Formatter;
 
+/// The store used for identifiers without a location.
+const DEFAULT_STORE_NO: &str = "A076";
+
 impl super::Formatter for Formatter {
     fn format(transactions: Vec<Transaction>) -> Result<String> {
         let mut wtr = Writer::from_writer(vec![]);
 
         for transaction in transactions {
+            let amount = transaction.money().to_string();
             let wasabi_tx = TransactionRecord {
-                store_no: "A076".to_owned(),
+                store_no: transaction
+                    .location_id
+                    .unwrap_or_else(|| DEFAULT_STORE_NO.to_owned()),
                 entry_no: "16277".to_owned(),
                 transaction_no: transaction.transaction_id,
-                tender_type: "3".to_owned(),
-                amount: to_pounds(transaction.amount),
+                tender_type: tender_type(transaction.transaction_type).to_owned(),
+                amount,
                 card_number: format!("{}******{}", transaction.first_six, transaction.last_four),
                 card_type_name: card_type_name(transaction.payment_provider.as_str()),
                 auth_code: transaction.auth_code.clone(),
@@ -64,6 +70,14 @@ impl super::Formatter for Formatter {
     }
 }
 
+/// Card payments are tender type 3, and card refunds tender type 4.
+const fn tender_type(transaction_type: TransactionType) -> &'static str {
+    match transaction_type {
+        TransactionType::Purchase | TransactionType::Auth | TransactionType::Settlement => "3",
+        TransactionType::Refund | TransactionType::Void => "4",
+    }
+}
+
 fn padded_random_int(raise_power: u32, num_chars: u32) -> String {
     let upper_value = 10_u64.pow(raise_power);
     let number = rand::thread_rng().gen_range(1..upper_value);
@@ -84,6 +98,7 @@ mod tests {
     use crate::formatters::Formatter as _;
 
     use super::*;
+
     use chrono::Utc;
     use pretty_assertions::assert_eq;
 
@@ -101,28 +116,22 @@ mod tests {
             Transaction {
                 amount: 260,
                 transaction_date: dt,
-                payment_provider: "visa".to_owned(),
                 merchant_name: "Bink toffee".to_owned(),
                 transaction_id: "1234567890987654321234567".to_owned(),
                 auth_code: "098765".to_owned(),
                 identifier: "1111111111".to_owned(),
-                identifier_type: "PRIMARY".to_owned(),
                 token: "a_token_001".to_owned(),
-                first_six: "123456".to_owned(),
-                last_four: "7890".to_owned(),
+                ..Transaction::fixture()
             },
             Transaction {
                 amount: 4267,
                 transaction_date: dt,
-                payment_provider: "visa".to_owned(),
                 merchant_name: "Bink toffee".to_owned(),
                 transaction_id: "12345678909887654".to_owned(),
                 auth_code: "023454".to_owned(),
                 identifier: "1111111112".to_owned(),
-                identifier_type: "PRIMARY".to_owned(),
                 token: "a_token_002".to_owned(),
-                first_six: "123456".to_owned(),
-                last_four: "7890".to_owned(),
+                ..Transaction::fixture()
             },
         ];
 
@@ -133,4 +142,47 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn wasabi_refund_has_refund_tender_type() -> Result<()> {
+        let refund = Transaction {
+            amount: -260,
+            merchant_name: "Bink toffee".to_owned(),
+            transaction_id: "12345678909887655".to_owned(),
+            auth_code: "098765".to_owned(),
+            identifier: "1111111111".to_owned(),
+            token: "a_token_001".to_owned(),
+            transaction_type: TransactionType::Refund,
+            original_transaction_id: Some("1234567890987654321234567".to_owned()),
+            ..Transaction::fixture()
+        };
+
+        let wasabi_tx = Formatter::format(vec![refund])?;
+        let row = wasabi_tx.lines().nth(1).unwrap_or_default();
+
+        assert!(row.starts_with("A076,16277,12345678909887655,4,-2.60,"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn wasabi_store_is_the_identifier_location() -> Result<()> {
+        let transaction = Transaction {
+            amount: 260,
+            merchant_name: "Bink toffee".to_owned(),
+            transaction_id: "12345678909887656".to_owned(),
+            auth_code: "098765".to_owned(),
+            identifier: "1111111111".to_owned(),
+            token: "a_token_001".to_owned(),
+            location_id: Some("B123".to_owned()),
+            ..Transaction::fixture()
+        };
+
+        let wasabi_tx = Formatter::format(vec![transaction])?;
+        let row = wasabi_tx.lines().nth(1).unwrap_or_default();
+
+        assert!(row.starts_with("B123,16277,12345678909887656,3,2.60,"));
+
+        Ok(())
+    }
 }
@@ -0,0 +1,173 @@
+use std::collections::HashSet;
+use std::io::Write;
+
+use color_eyre::{eyre::eyre, Result};
+use rand::Rng;
+
+use super::Scheme;
+use crate::transactor::pool::{IdentifierRecord, IdentifierType};
+
+/// How many times an identifier is redrawn when it clashes with one already generated.
+const UNIQUE_ATTEMPTS: usize = 1000;
+
+/// Generates the identifiers for `stores` stores per retailer, for each payment provider.
+///
+/// Each store has a location ID and merchant internal ID shared by all its identifiers. Visa
+/// knows a store by a numeric MID (PRIMARY), store ID (SECONDARY) and PSIMI, Amex by a 10-digit
+/// SE number and Mastercard by a location ID.
+///
+/// # Errors
+///
+/// Returns an error if the identifiers run out of unique values.
+pub fn generate_identifiers(
+    retailer_slugs: &[String],
+    payment_providers: &[Scheme],
+    stores: usize,
+    rng: &mut impl Rng,
+) -> Result<Vec<IdentifierRecord>> {
+    let mut used = HashSet::new();
+    let mut records = Vec::new();
+    for retailer_slug in retailer_slugs {
+        for store in 1..=stores {
+            let location_id = format!("store_{store:04}");
+            let merchant_internal_id = format!("{retailer_slug}_{store:04}");
+            for payment_provider in payment_providers {
+                let mut push = |identifier, identifier_type| {
+                    records.push(IdentifierRecord {
+                        retailer_slug: retailer_slug.clone(),
+                        payment_slug: payment_provider.slug().to_owned(),
+                        identifier,
+                        identifier_type,
+                        location_id: Some(location_id.clone()),
+                        merchant_internal_id: Some(merchant_internal_id.clone()),
+                    });
+                };
+                match payment_provider {
+                    Scheme::Visa => {
+                        push(
+                            unique_digits("", 8, &mut used, rng)?,
+                            IdentifierType::PrimaryMID,
+                        );
+                        push(
+                            unique_digits("", 9, &mut used, rng)?,
+                            IdentifierType::SecondaryMID,
+                        );
+                        push(unique_digits("", 8, &mut used, rng)?, IdentifierType::Psimi);
+                    }
+                    Scheme::Mastercard => {
+                        push(
+                            unique_digits("", 9, &mut used, rng)?,
+                            IdentifierType::PrimaryMID,
+                        );
+                    }
+                    Scheme::Amex => {
+                        push(
+                            unique_digits("9", 10, &mut used, rng)?,
+                            IdentifierType::PrimaryMID,
+                        );
+                    }
+                }
+            }
+        }
+    }
+    Ok(records)
+}
+
+/// Draws a number of `length` digits, starting with `prefix`, that has not been drawn before.
+fn unique_digits(
+    prefix: &str,
+    length: usize,
+    used: &mut HashSet<String>,
+    rng: &mut impl Rng,
+) -> Result<String> {
+    for _ in 0..UNIQUE_ATTEMPTS {
+        let mut digits = prefix.to_owned();
+        while digits.len() < length {
+            digits.push(char::from(b'0' + rng.gen_range(0..10)));
+        }
+        if used.insert(digits.clone()) {
+            return Ok(digits);
+        }
+    }
+    Err(eyre!("ran out of unique {length} digit identifiers"))
+}
+
+/// Writes identifiers in the headerless layout of `perf_mids.csv`.
+///
+/// # Errors
+///
+/// Returns an error if the identifiers cannot be written.
+pub fn write_identifiers(records: &[IdentifierRecord], writer: impl Write) -> Result<()> {
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(writer);
+    for record in records {
+        wtr.serialize(record)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use pretty_assertions::assert_eq;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::transactor::pool::{load_identifier_pools, IdentifierPool};
+
+    #[test]
+    fn identifiers_are_written_in_the_mids_file_layout() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("atalanta-mids-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        let path = dir.join("perf_mids.csv");
+
+        let records = generate_identifiers(
+            &["costa".to_owned(), "wasabi-club".to_owned()],
+            &Scheme::ALL,
+            3,
+            &mut StdRng::seed_from_u64(1),
+        )?;
+        let mut written = Vec::new();
+        write_identifiers(&records, &mut written)?;
+        fs::write(&path, &written)?;
+
+        // Visa has three identifiers per store, and Mastercard and Amex one each.
+        assert_eq!(records.len(), 2 * 3 * 5);
+        let first = &records[0];
+        assert_eq!(
+            String::from_utf8(written)?.lines().next(),
+            Some(
+                format!(
+                    "costa,visa,{},PRIMARY,store_0001,costa_0001",
+                    first.identifier
+                )
+                .as_str()
+            )
+        );
+        for record in &records {
+            assert!(record.identifier.chars().all(|c| c.is_ascii_digit()));
+            if record.payment_slug == "amex" {
+                assert_eq!(record.identifier.len(), 10);
+            }
+        }
+        let unique = records
+            .iter()
+            .map(|record| &record.identifier)
+            .collect::<HashSet<_>>();
+        assert_eq!(unique.len(), records.len());
+
+        let retailer_slugs = ["costa", "wasabi-club"].into_iter().collect::<HashSet<_>>();
+        let pools = load_identifier_pools(&path, &retailer_slugs)?;
+        let costa = pools.by_retailer.get("costa");
+        assert_eq!(costa.map(IdentifierPool::len), Some(15));
+        let store = costa.and_then(|pool| pool.store(first));
+        assert!(store.is_some_and(|store| store.secondary.is_some() && store.psimi.is_some()));
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
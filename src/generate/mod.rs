@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use color_eyre::{eyre::eyre, Result};
+use rand::Rng;
+
+pub mod mids;
+pub mod tokens;
+
+/// A card scheme, and the shape of the card numbers it issues.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scheme {
+    Visa,
+    Mastercard,
+    Amex,
+}
+
+impl Scheme {
+    pub const ALL: [Self; 3] = [Self::Visa, Self::Mastercard, Self::Amex];
+
+    /// The scheme's payment slug, as used in the tokens and MIDs files.
+    #[must_use]
+    pub const fn slug(self) -> &'static str {
+        match self {
+            Self::Visa => "visa",
+            Self::Mastercard => "mastercard",
+            Self::Amex => "amex",
+        }
+    }
+
+    #[must_use]
+    pub const fn pan_length(self) -> usize {
+        match self {
+            Self::Visa | Self::Mastercard => 16,
+            Self::Amex => 15,
+        }
+    }
+
+    /// Picks a first six from the scheme's BIN ranges: 4 for Visa, 51-55 and 2221-2720 for
+    /// Mastercard, and 34 or 37 for Amex.
+    pub fn first_six(self, rng: &mut impl Rng) -> String {
+        match self {
+            Self::Visa => format!("4{:05}", rng.gen_range(0..100_000)),
+            // Both Mastercard ranges hold 50,000 BINs, so they are picked from evenly.
+            Self::Mastercard => {
+                if rng.gen_bool(0.5) {
+                    format!("{}{:04}", rng.gen_range(51..=55), rng.gen_range(0..10_000))
+                } else {
+                    format!("{}{:02}", rng.gen_range(2221..=2720), rng.gen_range(0..100))
+                }
+            }
+            Self::Amex => format!(
+                "{}{:04}",
+                if rng.gen_bool(0.5) { 34 } else { 37 },
+                rng.gen_range(0..10_000)
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Scheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.slug())
+    }
+}
+
+impl FromStr for Scheme {
+    type Err = color_eyre::Report;
+
+    fn from_str(slug: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|scheme| scheme.slug() == slug)
+            .ok_or_else(|| eyre!("unknown payment scheme {slug}"))
+    }
+}
+
+/// The command line of a generator: a count, the retailers to generate for, and options.
+pub struct Command {
+    pub usage: &'static str,
+    /// What the count is of, for the error when it is not a number.
+    pub count_name: &'static str,
+    /// The option listing the schemes to generate for, e.g. `--schemes`.
+    pub schemes_option: &'static str,
+    /// Any other options that take a value.
+    pub options: &'static [&'static str],
+}
+
+/// What to generate, from the command line.
+pub struct Args {
+    pub count: usize,
+    pub retailer_slugs: Vec<String>,
+    pub schemes: Vec<Scheme>,
+    pub seed: Option<u64>,
+    /// The values of the command's other options, by name.
+    pub options: HashMap<&'static str, String>,
+}
+
+impl Command {
+    /// Parses `<count> <retailer>...`, with options anywhere after the count.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error with the usage if the count is missing or not a number, an option is
+    /// unknown or has no value, or no retailer is given.
+    pub fn parse(&self, mut args: impl Iterator<Item = String>) -> Result<Args> {
+        let usage = self.usage;
+        let count = args
+            .next()
+            .ok_or_else(|| eyre!("{usage}"))?
+            .parse()
+            .map_err(|e| eyre!("invalid {} count: {e}\n{usage}", self.count_name))?;
+
+        let mut parsed = Args {
+            count,
+            retailer_slugs: Vec::new(),
+            schemes: Scheme::ALL.to_vec(),
+            seed: None,
+            options: HashMap::new(),
+        };
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                parsed.retailer_slugs.push(arg);
+                continue;
+            }
+            let value = args
+                .next()
+                .ok_or_else(|| eyre!("{arg} needs a value\n{usage}"))?;
+            if arg == self.schemes_option {
+                parsed.schemes = value.split(',').map(str::parse).collect::<Result<_>>()?;
+            } else if arg == "--seed" {
+                parsed.seed = Some(value.parse()?);
+            } else if let Some(option) = self.options.iter().find(|option| **option == arg) {
+                parsed.options.insert(option, value);
+            } else {
+                return Err(eyre!("unknown option {arg}\n{usage}"));
+            }
+        }
+
+        if parsed.retailer_slugs.is_empty() {
+            return Err(eyre!("{usage}"));
+        }
+        Ok(parsed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    const COMMAND: Command = Command {
+        usage: "usage: gen <count> <retailer>...",
+        count_name: "token",
+        schemes_option: "--schemes",
+        options: &["--cards"],
+    };
+
+    fn parse(args: &[&str]) -> Result<Args> {
+        COMMAND.parse(args.iter().map(|arg| (*arg).to_owned()))
+    }
+
+    #[test]
+    fn arguments_are_parsed() -> Result<()> {
+        let args = parse(&[
+            "10",
+            "costa",
+            "--schemes",
+            "visa,amex",
+            "wasabi-club",
+            "--seed",
+            "1",
+            "--cards",
+            "cards.csv",
+        ])?;
+
+        assert_eq!(args.count, 10);
+        assert_eq!(args.retailer_slugs, vec!["costa", "wasabi-club"]);
+        assert_eq!(args.schemes, vec![Scheme::Visa, Scheme::Amex]);
+        assert_eq!(args.seed, Some(1));
+        assert_eq!(
+            args.options.get("--cards").map(String::as_str),
+            Some("cards.csv")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_options_are_rejected() {
+        assert!(parse(&["10", "costa", "--providers", "visa"]).is_err());
+        assert!(parse(&["10", "costa", "--seed"]).is_err());
+        assert!(parse(&["10"]).is_err());
+        assert!(parse(&["ten", "costa"]).is_err());
+    }
+}
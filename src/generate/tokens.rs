@@ -0,0 +1,240 @@
+use std::io::Write;
+
+use chrono::{Datelike, NaiveDate};
+use color_eyre::Result;
+use rand::Rng;
+use serde::Serialize;
+use uuid::Builder;
+
+use super::Scheme;
+use crate::transactor::pool::TokenRecord;
+
+/// A synthetic payment card and the token it is known by.
+#[derive(Clone, Debug)]
+pub struct Card {
+    pub record: TokenRecord,
+    pub pan: String,
+    /// The expiry date, as `MM/YY`.
+    pub expiry: String,
+}
+
+/// A card as written to the cards file, which keeps the PAN and expiry behind each token.
+#[derive(Serialize)]
+struct CardRow<'a> {
+    token: &'a str,
+    payment_slug: &'a str,
+    pan: &'a str,
+    expiry: &'a str,
+}
+
+/// Returns the digit that makes the given digits, with it appended, pass the Luhn check.
+#[must_use]
+pub fn luhn_check_digit(digits: &str) -> u32 {
+    // Counting from the right, every other digit is doubled, starting with the one before the
+    // check digit.
+    let sum = digits
+        .chars()
+        .rev()
+        .filter_map(|digit| digit.to_digit(10))
+        .enumerate()
+        .map(|(index, digit)| match (index % 2 == 0, digit * 2) {
+            (true, doubled) if doubled > 9 => doubled - 9,
+            (true, doubled) => doubled,
+            (false, _) => digit,
+        })
+        .sum::<u32>();
+    (10 - sum % 10) % 10
+}
+
+/// Whether a card number passes the Luhn check.
+#[must_use]
+pub fn luhn_valid(pan: &str) -> bool {
+    match pan.split_at_checked(pan.len().saturating_sub(1)) {
+        Some((digits, check)) if !digits.is_empty() && pan.chars().all(|c| c.is_ascii_digit()) => {
+            check == luhn_check_digit(digits).to_string()
+        }
+        _ => false,
+    }
+}
+
+/// Generates a card for the retailer, with a Luhn-valid PAN from the scheme's BIN ranges that
+/// expires within five years of `today`.
+pub fn generate_card(
+    retailer_slug: &str,
+    scheme: Scheme,
+    today: NaiveDate,
+    rng: &mut impl Rng,
+) -> Card {
+    let first_six = scheme.first_six(rng);
+    let mut pan = first_six.clone();
+    while pan.len() < scheme.pan_length() - 1 {
+        pan.push(char::from(b'0' + rng.gen_range(0..10)));
+    }
+    pan.push_str(&luhn_check_digit(&pan).to_string());
+
+    let expiry_year = today.year() + rng.gen_range(1..=5);
+    let expiry = format!("{:02}/{:02}", rng.gen_range(1..=12), expiry_year % 100);
+
+    Card {
+        record: TokenRecord {
+            token: Builder::from_random_bytes(rng.gen())
+                .into_uuid()
+                .to_string(),
+            retailer_slug: retailer_slug.to_owned(),
+            first_six,
+            last_four: pan[pan.len() - 4..].to_owned(),
+            payment_slug: scheme.slug().to_owned(),
+        },
+        pan,
+        expiry,
+    }
+}
+
+/// Generates `count` cards for every retailer and scheme.
+pub fn generate_cards(
+    retailer_slugs: &[String],
+    schemes: &[Scheme],
+    count: usize,
+    today: NaiveDate,
+    rng: &mut impl Rng,
+) -> Vec<Card> {
+    let mut cards = Vec::with_capacity(retailer_slugs.len() * schemes.len() * count);
+    for retailer_slug in retailer_slugs {
+        for scheme in schemes {
+            for _ in 0..count {
+                cards.push(generate_card(retailer_slug, *scheme, today, rng));
+            }
+        }
+    }
+    cards
+}
+
+/// Writes the cards' tokens in the headerless layout of `hermes_tokens.csv`.
+///
+/// # Errors
+///
+/// Returns an error if the tokens cannot be written.
+pub fn write_tokens(cards: &[Card], writer: impl Write) -> Result<()> {
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(writer);
+    for card in cards {
+        wtr.serialize(&card.record)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Writes each token's PAN and expiry date as a headered CSV.
+///
+/// # Errors
+///
+/// Returns an error if the cards cannot be written.
+pub fn write_cards(cards: &[Card], writer: impl Write) -> Result<()> {
+    let mut wtr = csv::Writer::from_writer(writer);
+    for card in cards {
+        wtr.serialize(CardRow {
+            token: &card.record.token,
+            payment_slug: &card.record.payment_slug,
+            pan: &card.pan,
+            expiry: &card.expiry,
+        })?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::fs;
+
+    use pretty_assertions::assert_eq;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::transactor::pool::{load_token_pools, TokenPool};
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 5, 1).unwrap_or_default()
+    }
+
+    #[test]
+    fn luhn_check_digits_match_known_numbers() {
+        assert_eq!(luhn_check_digit("7992739871"), 3);
+        assert!(luhn_valid("4111111111111111"));
+        assert!(luhn_valid("378282246310005"));
+        assert!(!luhn_valid("4111111111111112"));
+        assert!(!luhn_valid(""));
+    }
+
+    #[test]
+    fn cards_are_luhn_valid_and_in_their_scheme_ranges() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..1000 {
+            for scheme in Scheme::ALL {
+                let card = generate_card("costa", scheme, today(), &mut rng);
+                let first_six = &card.record.first_six;
+                let bin = first_six[..4].parse::<u32>().unwrap_or_default();
+
+                assert_eq!(card.pan.len(), scheme.pan_length());
+                assert!(luhn_valid(&card.pan), "{} is not Luhn valid", card.pan);
+                assert!(card.pan.starts_with(first_six.as_str()));
+                assert!(card.pan.ends_with(card.record.last_four.as_str()));
+                assert!(
+                    match scheme {
+                        Scheme::Visa => first_six.starts_with('4'),
+                        Scheme::Mastercard =>
+                            (5100..5600).contains(&bin) || (2221..=2720).contains(&bin),
+                        Scheme::Amex => first_six.starts_with("34") || first_six.starts_with("37"),
+                    },
+                    "{first_six} is not a {scheme} BIN"
+                );
+
+                let (month, year) = card.expiry.split_once('/').unwrap_or_default();
+                assert!((1..=12).contains(&month.parse::<u32>().unwrap_or_default()));
+                assert!((25..=29).contains(&year.parse::<u32>().unwrap_or_default()));
+            }
+        }
+    }
+
+    #[test]
+    fn tokens_are_written_in_the_tokens_file_layout() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("atalanta-tokens-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        let path = dir.join("hermes_tokens.csv");
+
+        let cards = generate_cards(
+            &["costa".to_owned(), "wasabi-club".to_owned()],
+            &[Scheme::Visa, Scheme::Amex],
+            3,
+            today(),
+            &mut StdRng::seed_from_u64(1),
+        );
+        let mut written = Vec::new();
+        write_tokens(&cards, &mut written)?;
+        fs::write(&path, &written)?;
+
+        let first_line = String::from_utf8(written)?;
+        let first_line = first_line.lines().next().unwrap_or_default();
+        let record = &cards[0].record;
+        assert_eq!(
+            first_line,
+            format!(
+                "{},costa,{},{},visa",
+                record.token, record.first_six, record.last_four
+            )
+        );
+
+        let retailer_slugs = ["costa", "wasabi-club"].into_iter().collect::<HashSet<_>>();
+        let pools = load_token_pools(&path, &retailer_slugs)?;
+        assert_eq!(pools.get("costa").map(TokenPool::len), Some(6));
+        assert!(pools
+            .get("wasabi-club")
+            .is_some_and(|pool| pool.has_provider("amex")));
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
@@ -10,6 +10,8 @@ pub fn startup() -> Result<()> {
     tracing_subscriber::fmt()
         .with_max_level(tracing::Level::DEBUG)
         .with_target(false)
+        // Logs go to stderr, so that stdout can be piped into other tools.
+        .with_writer(std::io::stderr)
         .init();
 
     Ok(())
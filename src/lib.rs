@@ -2,7 +2,10 @@ pub mod amqp;
 pub mod configuration;
 pub mod consumers;
 pub mod formatters;
+pub mod generate;
 pub mod initialise;
 pub mod models;
+pub mod reconcile;
 pub mod senders;
 pub mod services;
+pub mod transactor;
@@ -1,12 +1,391 @@
+use chrono::{DateTime, NaiveTime, Utc};
+use color_eyre::{eyre::eyre, Result};
+use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::time::Duration;
+
+use super::money::Currency;
+use super::payment::{Transaction, TransactionType};
 
 #[derive(serde::Deserialize)]
 pub struct TransactorConfig {
+    /// Retailers to generate transactions for. A config without a `retailers` list describes a
+    /// single retailer with top-level fields, and is loaded as a list of one.
+    #[serde(default)]
+    pub retailers: Vec<RetailerConfig>,
+
+    /// Total rate across all retailers. Defaults to the sum of the retailers' rates.
+    pub transactions_per_second: Option<u64>,
+
+    /// Load profile phases to run in order. If empty, the transactor runs forever at a constant
+    /// `transactions_per_second`.
+    #[serde(default)]
+    pub phases: Vec<LoadPhase>,
+
+    /// Number of threads publishing to `RabbitMQ`, each on its own channel.
+    #[serde(default = "default_publish_workers")]
+    pub publish_workers: usize,
+
+    /// How many messages each publish worker may have waiting for a confirm from `RabbitMQ`.
+    #[serde(default = "default_max_in_flight")]
+    pub max_in_flight: usize,
+
+    /// Stop after publishing this many transactions.
+    pub total_transactions: Option<u64>,
+
+    /// Stop after running for this many seconds.
+    pub duration_secs: Option<u64>,
+
+    /// Seeds transaction generation, so the same seed, tokens and MIDs give the same transactions.
+    pub seed: Option<u64>,
+
+    /// Dates transactions from this point on a simulated clock instead of the wall clock.
+    pub start_date: Option<DateTime<Utc>>,
+
+    /// Rates of deliberately bad transactions that should not match.
+    #[serde(default)]
+    pub injection: InjectionConfig,
+
+    /// Records every published transaction and whether it should match.
+    pub ledger: Option<LedgerConfig>,
+
+    /// Publishes transactions from a file instead of generating them.
+    pub replay: Option<ReplayConfig>,
+
+    /// Where transactions are sent. Defaults to `RabbitMQ`.
+    #[serde(default)]
+    pub sink: SinkConfig,
+
+    /// Serves Prometheus metrics while the transactor runs.
+    pub metrics: Option<MetricsConfig>,
+
+    /// Serves an HTTP API to pause, resume and reshape the load while the transactor runs.
+    pub control: Option<ControlConfig>,
+}
+
+/// Where the transactor sends transactions, e.g. `sink = "Stdout"` or
+/// `sink.File = { path = "transactions.jsonl" }`.
+#[derive(serde::Deserialize, Clone, Debug, Default)]
+pub enum SinkConfig {
+    /// The `transactions` exchange, for the distributors.
+    #[default]
+    Amqp,
+    /// Appends a JSON line per transaction to a file.
+    File { path: PathBuf },
+    /// Writes a JSON line per transaction to stdout.
+    Stdout,
+}
+
+const fn default_publish_workers() -> usize {
+    1
+}
+
+const fn default_max_in_flight() -> usize {
+    1000
+}
+
+/// Replays captured transactions either with their original timing, sped up or slowed down by
+/// `speed`, or at a fixed `rate` per second.
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct ReplayConfig {
+    /// A JSONL file, such as a ledger, or a headered CSV file of transactions.
+    pub path: PathBuf,
+
+    /// How many times faster than the original to replay. Defaults to 1.
+    pub speed: Option<f64>,
+
+    /// Transactions per second, ignoring the original timing.
+    pub rate: Option<f64>,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct MetricsConfig {
+    /// The address to serve `/metrics` on.
+    #[serde(default = "default_metrics_address")]
+    pub address: SocketAddr,
+}
+
+fn default_metrics_address() -> SocketAddr {
+    SocketAddr::from(([0, 0, 0, 0], 9100))
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct ControlConfig {
+    /// The address to serve the control API on.
+    #[serde(default = "default_control_address")]
+    pub address: SocketAddr,
+}
+
+fn default_control_address() -> SocketAddr {
+    SocketAddr::from(([0, 0, 0, 0], 8003))
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct LedgerConfig {
+    /// The JSONL file to append to. Full files are moved aside to `<path>.1`, `<path>.2` and so on.
+    pub path: PathBuf,
+
+    /// Rotate the ledger once it grows past this many bytes.
+    #[serde(default = "default_ledger_max_bytes")]
+    pub max_bytes: u64,
+
+    /// Identifies the run in every entry. Defaults to a random UUID.
+    pub run_id: Option<String>,
+}
+
+const fn default_ledger_max_bytes() -> u64 {
+    100 * 1024 * 1024
+}
+
+/// The fraction of transactions to spoil in each way. At most one fault is injected into any
+/// transaction, so the rates must add up to no more than 1.
+#[derive(serde::Deserialize, Clone, Debug, Default)]
+pub struct InjectionConfig {
+    /// Tokens that are not in the tokens file.
+    #[serde(default)]
+    pub unknown_token: f64,
+    /// Identifiers that are not in the MIDs file.
+    #[serde(default)]
+    pub unknown_mid: f64,
+    /// Transaction IDs already used by an earlier transaction.
+    #[serde(default)]
+    pub duplicate_transaction_id: f64,
+    #[serde(default)]
+    pub zero_amount: f64,
+    #[serde(default)]
+    pub negative_amount: f64,
+    /// First six and last four digits that do not belong to the token.
+    #[serde(default)]
+    pub mismatched_card: f64,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct RetailerConfig {
     pub provider_slug: String,
-    pub amount_min: i64,
-    pub amount_max: i64,
-    pub transactions_per_second: u64,
-    pub percentage: [(String, i32); 3],
+
+    /// Bounds of the default uniform amount distribution, used when `amounts` is not set.
+    pub amount_min: Option<i64>,
+    pub amount_max: Option<i64>,
+
+    /// Distribution to draw amounts from, in minor units.
+    pub amounts: Option<AmountDistribution>,
+
+    /// Amount distributions that replace the retailer's for specific payment providers.
+    #[serde(default)]
+    pub provider_amounts: HashMap<String, AmountDistribution>,
+
+    /// Weighted mix of payment schemes, e.g. `[['visa', 80], ['amex', 20]]`.
+    pub percentage: Vec<(String, u32)>,
+
+    /// Weighted mix of currencies, e.g. `[['GBP', 90], ['EUR', 10]]`. Defaults to GBP.
+    #[serde(default)]
+    pub currencies: Vec<(Currency, u32)>,
+
+    /// Optional weighted mix of identifier types, e.g. `[['PRIMARY', 90], ['PSIMI', 10]]`.
+    /// Without it, identifiers are picked regardless of type.
+    pub identifier_type_percentage: Option<Vec<(String, u32)>>,
+
+    /// This retailer's share of the total rate. Defaults to `transactions_per_second`, or 1.
+    pub weight: Option<f64>,
+    pub transactions_per_second: Option<u64>,
+
+    /// Fraction of the retailer's transactions that refund an earlier purchase.
+    #[serde(default)]
+    pub refund_rate: f64,
+
+    /// Fraction of refunds that return only part of the purchase amount.
+    #[serde(default)]
+    pub partial_refund_rate: f64,
+
+    /// Fraction of the retailer's transactions that void an earlier purchase.
+    #[serde(default)]
+    pub void_rate: f64,
+
+    /// Sends the retailer's purchases as an auth followed by a settlement.
+    pub settlement: Option<SettlementConfig>,
+
+    /// Shapes transaction dates around the retailer's trading hours. Without it, transactions
+    /// are dated by the clock alone.
+    pub timestamps: Option<TimestampConfig>,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct SettlementConfig {
+    /// Payment providers whose purchases are split into auths and settlements. Defaults to all.
+    #[serde(default)]
+    pub payment_providers: Vec<String>,
+
+    /// How long after its auth a settlement is sent.
+    #[serde(default = "default_settlement_lag_secs")]
+    pub lag_secs: u32,
+
+    /// Adds up to this many seconds of random lag on top of `lag_secs`.
+    #[serde(default)]
+    pub lag_jitter_secs: u32,
+
+    /// Fraction of settlements that add a tip of up to `max_tip_percent` to the auth amount.
+    #[serde(default)]
+    pub tip_rate: f64,
+    #[serde(default = "default_max_tip_percent")]
+    pub max_tip_percent: u32,
+
+    /// Fraction of settlements for only part of the auth amount.
+    #[serde(default)]
+    pub partial_rate: f64,
+
+    /// Fraction of auths that are never settled.
+    #[serde(default)]
+    pub never_settle_rate: f64,
+}
+
+const fn default_settlement_lag_secs() -> u32 {
+    10
+}
+
+const fn default_max_tip_percent() -> u32 {
+    20
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct TimestampConfig {
+    /// IANA name of the retailer's local timezone.
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+
+    /// Local `['open', 'close']` times, e.g. `['08:00', '22:00']`. A closing time before the
+    /// opening time means the store trades past midnight. Transactions dated outside these hours
+    /// are moved back into the most recent opening period.
+    pub opening_hours: Option<(NaiveTime, NaiveTime)>,
+
+    /// Dates every transaction this many seconds before it is generated.
+    #[serde(default)]
+    pub lag_secs: u32,
+
+    /// Adds up to this many seconds of random lag on top of `lag_secs`.
+    #[serde(default)]
+    pub lag_jitter_secs: u32,
+
+    /// Fraction of transactions dated close to the previous local midnight or DST transition
+    /// instead, regardless of opening hours.
+    #[serde(default)]
+    pub boundary_rate: f64,
+
+    /// How far either side of a boundary those transactions may fall.
+    #[serde(default = "default_boundary_window_secs")]
+    pub boundary_window_secs: u32,
+}
+
+fn default_timezone() -> String {
+    "Europe/London".to_owned()
+}
+
+const fn default_boundary_window_secs() -> u32 {
+    300
+}
+
+/// A distribution of transaction amounts in minor units.
+#[derive(serde::Deserialize, Clone, Debug)]
+pub enum AmountDistribution {
+    /// Uniform between `min` (inclusive) and `max` (exclusive).
+    Uniform { min: i64, max: i64 },
+    /// Normal with the given `mean` and `std_dev`, clamped to `min` and `max`.
+    Normal {
+        mean: f64,
+        std_dev: f64,
+        min: Option<i64>,
+        max: Option<i64>,
+    },
+    /// Log-normal with the given `median` and shape `sigma`, clamped to `min` and `max`.
+    /// Real basket sizes tend to follow this shape.
+    LogNormal {
+        median: f64,
+        sigma: f64,
+        min: Option<i64>,
+        max: Option<i64>,
+    },
+    /// Picks one of a fixed list of amounts with equal probability.
+    Fixed(Vec<i64>),
+    /// Weighted `[from, to, weight]` buckets, with a uniform amount inside each bucket.
+    Histogram(Vec<(i64, i64, u32)>),
+}
+
+impl RetailerConfig {
+    /// Returns the retailer's amount distribution, falling back to a uniform distribution
+    /// between `amount_min` and `amount_max`.
+    #[must_use]
+    pub fn amount_distribution(&self) -> Option<AmountDistribution> {
+        self.amounts.clone().or_else(|| {
+            Some(AmountDistribution::Uniform {
+                min: self.amount_min?,
+                max: self.amount_max?,
+            })
+        })
+    }
+
+    #[must_use]
+    pub fn weight(&self) -> f64 {
+        self.weight
+            .or_else(|| self.transactions_per_second.map(|tps| tps as f64))
+            .unwrap_or(1.0)
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct LoadPhase {
+    pub name: Option<String>,
+    pub shape: LoadShape,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub enum LoadShape {
+    /// A constant rate for the whole phase.
+    Soak { rate: f64, duration_secs: u64 },
+    /// A linear change from one rate to another over the phase.
+    Ramp {
+        from: f64,
+        to: f64,
+        duration_secs: u64,
+    },
+    /// Starts at `from` and increases by `step` every `step_secs`, for `steps` steps.
+    Step {
+        from: f64,
+        step: f64,
+        step_secs: u64,
+        steps: u64,
+    },
+    /// A `base` rate with a burst to `peak` starting `offset_secs` into the phase.
+    Spike {
+        base: f64,
+        peak: f64,
+        offset_secs: u64,
+        spike_secs: u64,
+        duration_secs: u64,
+    },
+    /// A sine wave of `amplitude` around `mean`.
+    Sine {
+        mean: f64,
+        amplitude: f64,
+        period_secs: u64,
+        duration_secs: u64,
+    },
+    /// Replays an hourly volume curve from a headerless `hour,transactions` CSV file.
+    /// Each hour lasts `seconds_per_hour` and its rate is multiplied by `scale`.
+    Trace {
+        path: PathBuf,
+        #[serde(default = "default_seconds_per_hour")]
+        seconds_per_hour: u64,
+        #[serde(default = "default_trace_scale")]
+        scale: f64,
+    },
+}
+
+const fn default_seconds_per_hour() -> u64 {
+    3600
+}
+
+const fn default_trace_scale() -> f64 {
+    1.0
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -15,9 +394,165 @@ pub struct DistributorConfig {
     pub routing_key: String,
     pub batch_size: usize,
 
+    /// How transactions are taken off the queue. Defaults to the usual consumer for
+    /// `provider_slug`.
+    pub consumer: Option<ConsumerKind>,
+
+    /// The format transactions are sent in. Defaults to the usual formatter for `provider_slug`.
+    pub formatter: Option<FormatterKind>,
+
+    /// How long the `Delay` consumer holds each transaction for. Defaults to 10 seconds.
+    pub delay_secs: Option<u64>,
+
+    /// The transaction types sent on. Others are taken off the queue and dropped, so that an auth
+    /// distributor can leave settlements alone. Defaults to every type.
+    #[serde(default)]
+    pub transaction_types: Vec<TransactionType>,
+
     pub sender: SenderConfig,
 }
 
+/// How long the `Delay` consumer holds each transaction for, unless `delay_secs` is set.
+const DEFAULT_DELAY_SECS: u64 = 10;
+
+/// How a distributor takes transactions off its queue.
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConsumerKind {
+    /// Sends each transaction as soon as it arrives.
+    Instant,
+    /// Sends up to `batch_size` transactions at a time.
+    Batch,
+    /// Sends each transaction once it is `delay_secs` old.
+    Delay,
+}
+
+/// The formatters a distributor can send transactions with.
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FormatterKind {
+    Costa,
+    Stonegate,
+    TgiFridays,
+    Wasabi,
+    Iceland,
+    VisaAuth,
+    VisaSettlement,
+    AmexAuth,
+    AmexSettlement,
+}
+
+/// The consumer and formatter a distributor runs, with the consumer's delay if it has one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pipeline {
+    pub consumer: ConsumerKind,
+    pub formatter: FormatterKind,
+    pub delay: Option<Duration>,
+    /// The transaction types sent on, or every type if empty.
+    pub transaction_types: Vec<TransactionType>,
+}
+
+impl FormatterKind {
+    /// Whether the format can carry a refund or void. Iceland files and Amex auths cannot.
+    #[must_use]
+    pub const fn sends_reversals(self) -> bool {
+        !matches!(self, Self::Iceland | Self::AmexAuth)
+    }
+}
+
+impl DistributorConfig {
+    /// Whether the distributor sends transactions of this one's type.
+    #[must_use]
+    pub fn accepts(&self, transaction: &Transaction) -> bool {
+        self.transaction_types.is_empty()
+            || self
+                .transaction_types
+                .contains(&transaction.transaction_type)
+    }
+
+    /// Returns the consumer and formatter to run, filling in the usual ones for `provider_slug`
+    /// where they are not configured.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `provider_slug` has no usual pipeline to fill in from, `delay_secs`
+    /// is set for a consumer other than `Delay`, or `transaction_types` lists refunds or voids
+    /// for a formatter that cannot send them.
+    pub fn pipeline(&self) -> Result<Pipeline> {
+        let slug = &self.provider_slug;
+        let (consumer, formatter) = match (self.consumer, self.formatter) {
+            (Some(consumer), Some(formatter)) => (consumer, formatter),
+            (consumer, formatter) => {
+                let (default_consumer, default_formatter) =
+                    default_pipeline(slug).ok_or_else(|| {
+                        eyre!("{slug} has no usual pipeline, so set both consumer and formatter")
+                    })?;
+                (
+                    consumer.unwrap_or(default_consumer),
+                    formatter.unwrap_or(default_formatter),
+                )
+            }
+        };
+
+        let delay = match (consumer, self.delay_secs) {
+            (ConsumerKind::Delay, delay_secs) => Some(Duration::from_secs(
+                delay_secs.unwrap_or(DEFAULT_DELAY_SECS),
+            )),
+            (_, None) => None,
+            (_, Some(_)) => {
+                return Err(eyre!(
+                    "delay_secs is set for {slug}, but only the Delay consumer has a delay"
+                ))
+            }
+        };
+
+        // Formatters that cannot send reversals leave them out unless told otherwise, so that one
+        // reaching the queue is dropped rather than failing the distributor.
+        let reversal = |transaction_type: &TransactionType| {
+            matches!(
+                transaction_type,
+                TransactionType::Refund | TransactionType::Void
+            )
+        };
+        let transaction_types = if formatter.sends_reversals() {
+            self.transaction_types.clone()
+        } else if self.transaction_types.is_empty() {
+            vec![
+                TransactionType::Purchase,
+                TransactionType::Auth,
+                TransactionType::Settlement,
+            ]
+        } else if self.transaction_types.iter().any(reversal) {
+            return Err(eyre!(
+                "transaction_types for {slug} lists refunds or voids, which {formatter:?} cannot send"
+            ));
+        } else {
+            self.transaction_types.clone()
+        };
+
+        Ok(Pipeline {
+            consumer,
+            formatter,
+            delay,
+            transaction_types,
+        })
+    }
+}
+
+/// The consumer and formatter each provider slug has always run with.
+fn default_pipeline(provider_slug: &str) -> Option<(ConsumerKind, FormatterKind)> {
+    let pipeline = match provider_slug {
+        "costa" => (ConsumerKind::Instant, FormatterKind::Costa),
+        "stonegate" => (ConsumerKind::Instant, FormatterKind::Stonegate),
+        "tgi-fridays" => (ConsumerKind::Instant, FormatterKind::TgiFridays),
+        "wasabi-club" => (ConsumerKind::Batch, FormatterKind::Wasabi),
+        "iceland-bonus-card" => (ConsumerKind::Batch, FormatterKind::Iceland),
+        "visa-auth" => (ConsumerKind::Instant, FormatterKind::VisaAuth),
+        "visa-settlement" => (ConsumerKind::Delay, FormatterKind::VisaSettlement),
+        "amex-auth" => (ConsumerKind::Instant, FormatterKind::AmexAuth),
+        _ => return None,
+    };
+    Some(pipeline)
+}
+
 #[derive(serde::Deserialize, Clone)]
 pub enum SenderConfig {
     API(APISenderConfig),
@@ -1,10 +1,14 @@
 mod configuration;
+mod money;
 mod payment;
 mod settings;
 
 pub use configuration::{
-    APISenderConfig, APISenderHeader, APISenderHeaderValue, BlobSenderConfig, DistributorConfig,
-    SFTPSenderConfig, SenderConfig, TransactorConfig,
+    APISenderConfig, APISenderHeader, APISenderHeaderValue, AmountDistribution, BlobSenderConfig,
+    ConsumerKind, ControlConfig, DistributorConfig, FormatterKind, InjectionConfig, LedgerConfig,
+    LoadPhase, LoadShape, MetricsConfig, Pipeline, ReplayConfig, RetailerConfig, SFTPSenderConfig,
+    SenderConfig, SettlementConfig, SinkConfig, TimestampConfig, TransactorConfig,
 };
-pub use payment::Transaction;
+pub use money::{Currency, Money};
+pub use payment::{StoreIdentifiers, Transaction, TransactionType};
 pub use settings::Settings;
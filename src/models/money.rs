@@ -0,0 +1,117 @@
+use std::fmt;
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// An ISO 4217 currency.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Currency {
+    #[default]
+    GBP,
+    EUR,
+    USD,
+    /// Yen have no minor unit.
+    JPY,
+    /// Dinars have three minor digits.
+    BHD,
+}
+
+impl Currency {
+    /// The three letter ISO 4217 code, e.g. `GBP`.
+    #[must_use]
+    pub const fn alpha_code(self) -> &'static str {
+        match self {
+            Self::GBP => "GBP",
+            Self::EUR => "EUR",
+            Self::USD => "USD",
+            Self::JPY => "JPY",
+            Self::BHD => "BHD",
+        }
+    }
+
+    /// The ISO 4217 numeric code, e.g. 826 for `GBP`.
+    #[must_use]
+    pub const fn numeric_code(self) -> u16 {
+        match self {
+            Self::GBP => 826,
+            Self::EUR => 978,
+            Self::USD => 840,
+            Self::JPY => 392,
+            Self::BHD => 48,
+        }
+    }
+
+    /// The numeric code as the three digit string most schemes expect, e.g. `048` for `BHD`.
+    #[must_use]
+    pub fn numeric_string(self) -> String {
+        format!("{:03}", self.numeric_code())
+    }
+
+    /// The number of digits after the decimal point in an amount.
+    #[must_use]
+    pub const fn minor_units(self) -> u32 {
+        match self {
+            Self::GBP | Self::EUR | Self::USD => 2,
+            Self::JPY => 0,
+            Self::BHD => 3,
+        }
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.alpha_code())
+    }
+}
+
+/// An amount in the minor units of a currency, displayed in major units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Money {
+    pub amount: i64,
+    pub currency: Currency,
+}
+
+impl Money {
+    #[must_use]
+    pub const fn new(amount: i64, currency: Currency) -> Self {
+        Self { amount, currency }
+    }
+
+    /// The same amount without its sign, for schemes that mark refunds some other way.
+    #[must_use]
+    pub const fn abs(self) -> Self {
+        Self::new(self.amount.abs(), self.currency)
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            Decimal::new(self.amount, self.currency.minor_units())
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn money_is_displayed_in_major_units() {
+        assert_eq!(Money::new(235, Currency::GBP).to_string(), "2.35");
+        assert_eq!(Money::new(-260, Currency::EUR).to_string(), "-2.60");
+        assert_eq!(Money::new(235, Currency::JPY).to_string(), "235");
+        assert_eq!(Money::new(2350, Currency::BHD).to_string(), "2.350");
+        assert_eq!(Money::new(-260, Currency::EUR).abs().to_string(), "2.60");
+    }
+
+    #[test]
+    fn numeric_codes_are_three_digits() {
+        assert_eq!(Currency::GBP.numeric_string(), "826");
+        assert_eq!(Currency::BHD.numeric_string(), "048");
+    }
+}
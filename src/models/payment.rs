@@ -1,6 +1,8 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use super::money::{Currency, Money};
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Transaction {
     pub amount: i64,
@@ -14,4 +16,132 @@ pub struct Transaction {
     pub token: String,
     pub first_six: String,
     pub last_four: String,
+    #[serde(default)]
+    pub transaction_type: TransactionType,
+    /// The `transaction_id` of the purchase a refund or void reverses.
+    #[serde(default)]
+    pub original_transaction_id: Option<String>,
+    #[serde(default)]
+    pub currency: Currency,
+    /// The retailer's ID for the store the identifier belongs to, where Harmonia has one.
+    #[serde(default)]
+    pub location_id: Option<String>,
+    /// The retailer's internal ID for the merchant, where Harmonia has one.
+    #[serde(default)]
+    pub merchant_internal_id: Option<String>,
+    /// Every identifier of the store the transaction was made at, where the store is known.
+    #[serde(default)]
+    pub store_identifiers: Option<StoreIdentifiers>,
+}
+
+impl Transaction {
+    /// The transaction's amount in its currency.
+    #[must_use]
+    pub const fn money(&self) -> Money {
+        Money::new(self.amount, self.currency)
+    }
+
+    /// Whether the transaction is a refund or void of an earlier purchase.
+    #[must_use]
+    pub const fn is_reversal(&self) -> bool {
+        matches!(
+            self.transaction_type,
+            TransactionType::Refund | TransactionType::Void
+        )
+    }
+
+    /// The store the transaction was made at, as the retailer knows it. Identifiers without a
+    /// location fall back to the identifier itself.
+    #[must_use]
+    pub fn retailer_location_id(&self) -> &str {
+        self.location_id.as_deref().unwrap_or(&self.identifier)
+    }
+
+    /// Returns the store's identifier of the given type: the transaction's own identifier if it
+    /// is of that type, or else one from the store's identifiers.
+    #[must_use]
+    pub fn identifier_of_type(&self, identifier_type: &str) -> Option<&str> {
+        if self.identifier_type == identifier_type {
+            return Some(&self.identifier);
+        }
+        let store = self.store_identifiers.as_ref()?;
+        match identifier_type {
+            "PRIMARY" => store.primary.as_deref(),
+            "SECONDARY" => store.secondary.as_deref(),
+            "PSIMI" => store.psimi.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// The key the transaction is published to the `transactions` exchange with. Auths and
+    /// settlements share the key of the purchase they are split from, and a distributor that
+    /// wants only one half filters on `transaction_type`.
+    #[must_use]
+    pub fn routing_key(&self) -> String {
+        format!(
+            "transactions.{}.{}",
+            self.payment_provider, self.merchant_name
+        )
+    }
+}
+
+/// The identifiers a payment provider has for one store: Visa, for example, knows a store by its
+/// MID, store ID and PSIMI at once.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq)]
+pub struct StoreIdentifiers {
+    pub primary: Option<String>,
+    pub secondary: Option<String>,
+    pub psimi: Option<String>,
+}
+
+#[cfg(test)]
+impl Transaction {
+    /// A GBP purchase of 2.45 at a PRIMARY MID, for tests to build variants of with
+    /// `..Transaction::fixture()`.
+    #[must_use]
+    pub fn fixture() -> Self {
+        Self {
+            amount: 245,
+            transaction_date: Utc::now(),
+            payment_provider: "visa".to_owned(),
+            merchant_name: "test_merchant".to_owned(),
+            transaction_id: "test_transaction_id_1".to_owned(),
+            auth_code: "123456".to_owned(),
+            identifier: "12345678".to_owned(),
+            identifier_type: "PRIMARY".to_owned(),
+            token: "98765432123456789".to_owned(),
+            first_six: "123456".to_owned(),
+            last_four: "7890".to_owned(),
+            transaction_type: TransactionType::Purchase,
+            original_transaction_id: None,
+            currency: Currency::GBP,
+            location_id: None,
+            merchant_internal_id: None,
+            store_identifiers: None,
+        }
+    }
+}
+
+/// A purchase stands alone, while an auth is followed by a settlement with the same transaction
+/// ID. Refunds and voids reverse an earlier purchase or settlement, and carry a negative amount.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TransactionType {
+    #[default]
+    Purchase,
+    Auth,
+    Settlement,
+    Refund,
+    Void,
+}
+
+impl std::fmt::Display for TransactionType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Purchase => write!(f, "purchase"),
+            Self::Auth => write!(f, "auth"),
+            Self::Settlement => write!(f, "settlement"),
+            Self::Refund => write!(f, "refund"),
+            Self::Void => write!(f, "void"),
+        }
+    }
 }
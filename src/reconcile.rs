@@ -0,0 +1,620 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use color_eyre::{eyre::eyre, Result};
+use hdrhistogram::Histogram;
+use serde::Deserialize;
+
+use crate::models::TransactionType;
+use crate::transactor::injection::Injection;
+use crate::transactor::ledger::{ledger_files, LedgerEntry};
+
+/// Latency percentiles reported for each breakdown.
+const LATENCY_PERCENTILES: [f64; 4] = [50.0, 90.0, 99.0, 100.0];
+
+/// A transaction Harmonia exported, as found in a CSV or JSONL dump of its export table.
+#[derive(Debug, Deserialize)]
+pub struct ExportedTransaction {
+    pub transaction_id: String,
+    /// The retailer Harmonia matched the transaction to.
+    #[serde(default)]
+    pub provider_slug: Option<String>,
+    #[serde(default, alias = "exported_at")]
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// Reads the entries of one run from a ledger and its rotated files. Without a run ID, the run
+/// that was recorded last is read.
+///
+/// # Errors
+///
+/// Returns an error if the ledger cannot be read, holds an invalid entry, or has no entries for
+/// the run.
+pub fn read_ledger(path: &Path, run_id: Option<&str>) -> Result<Vec<LedgerEntry>> {
+    let mut entries = Vec::new();
+    for file in ledger_files(path) {
+        let reader = BufReader::new(File::open(&file)?);
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: LedgerEntry = serde_json::from_str(&line).map_err(|e| {
+                eyre!(
+                    "invalid ledger entry at {}:{}: {e}",
+                    file.display(),
+                    number + 1
+                )
+            })?;
+            entries.push(entry);
+        }
+    }
+
+    let run_id = match run_id {
+        Some(run_id) => run_id.to_owned(),
+        None => entries
+            .last()
+            .map(|entry| entry.run_id.clone())
+            .ok_or_else(|| eyre!("ledger {} is empty", path.display()))?,
+    };
+    entries.retain(|entry| entry.run_id == run_id);
+    if entries.is_empty() {
+        return Err(eyre!(
+            "ledger {} has no entries for run {run_id}",
+            path.display()
+        ));
+    }
+
+    Ok(entries)
+}
+
+/// Reads Harmonia's exported transactions from a JSONL file, or a headered CSV file if the
+/// extension is anything other than `jsonl` or `json`.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or holds an invalid record.
+pub fn read_export(path: &Path) -> Result<Vec<ExportedTransaction>> {
+    let file = File::open(path)?;
+    let jsonl = path
+        .extension()
+        .is_some_and(|extension| extension == "jsonl" || extension == "json");
+
+    if jsonl {
+        BufReader::new(file)
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| line.as_ref().map_or(true, |line| !line.trim().is_empty()))
+            .map(|(number, line)| {
+                serde_json::from_str(&line?).map_err(|e| {
+                    eyre!(
+                        "invalid export record at {}:{}: {e}",
+                        path.display(),
+                        number + 1
+                    )
+                })
+            })
+            .collect()
+    } else {
+        csv::Reader::from_reader(file)
+            .deserialize()
+            .map(|record| {
+                record.map_err(|e| eyre!("invalid export record in {}: {e}", path.display()))
+            })
+            .collect()
+    }
+}
+
+/// Counts of how one slice of a run's transactions fared.
+pub struct Tally {
+    /// Transactions that should have been matched.
+    pub expected: u64,
+    pub matched: u64,
+    pub missing: u64,
+    /// Transactions exported that should not have been, or for the wrong retailer.
+    pub false_matches: u64,
+    /// Injected duplicates of an earlier transaction ID.
+    pub duplicates: u64,
+    /// Injected duplicates that Harmonia exported as well as the original.
+    pub duplicates_exported: u64,
+    /// Milliseconds from publishing to export, for matched transactions Harmonia dated.
+    pub latencies: Histogram<u64>,
+}
+
+impl Default for Tally {
+    fn default() -> Self {
+        Self {
+            expected: 0,
+            matched: 0,
+            missing: 0,
+            false_matches: 0,
+            duplicates: 0,
+            duplicates_exported: 0,
+            // latencies grow the histogram as needed
+            latencies: Histogram::new(3)
+                .expect("3 significant figures is a valid histogram precision"),
+        }
+    }
+}
+
+impl Tally {
+    /// The fraction of expected transactions that were matched.
+    #[must_use]
+    pub fn match_rate(&self) -> f64 {
+        if self.expected == 0 {
+            return 0.0;
+        }
+        self.matched as f64 / self.expected as f64
+    }
+}
+
+enum Outcome {
+    Matched {
+        latency_ms: Option<u64>,
+    },
+    Missing,
+    FalseMatch,
+    /// Not expected to match, and not exported.
+    Rejected,
+    /// An injected duplicate that Harmonia exported only once, as it should.
+    Deduplicated,
+    /// An injected duplicate that Harmonia exported again.
+    DuplicateExported,
+}
+
+/// How Harmonia's exports compare to what a run's ledger says should have been matched, broken
+/// down by retailer, payment provider and identifier type.
+#[derive(Default)]
+pub struct Reconciliation {
+    pub run_id: String,
+    pub total: Tally,
+    pub by_retailer: BTreeMap<String, Tally>,
+    pub by_payment_provider: BTreeMap<String, Tally>,
+    pub by_identifier_type: BTreeMap<String, Tally>,
+    /// Keyed by the fault injected into a transaction, or else by its type, so that a refund is
+    /// told apart from a purchase given a negative amount.
+    pub by_kind: BTreeMap<String, Tally>,
+    /// Exported transactions that are not in the ledger at all.
+    pub unknown_exports: u64,
+}
+
+impl Reconciliation {
+    /// Compares the entries of one run's ledger with Harmonia's exports.
+    ///
+    /// An auth and its settlement share a transaction ID, and are counted once, as a transaction
+    /// that should match if either should. Every other entry is counted on its own, so that an
+    /// injected duplicate is checked apart from the transaction whose ID it reuses.
+    #[must_use]
+    pub fn new(ledger: &[LedgerEntry], exported: &[ExportedTransaction]) -> Self {
+        let mut exports: HashMap<&str, Exports> = HashMap::new();
+        for export in exported {
+            exports
+                .entry(&export.transaction_id)
+                .and_modify(|exports| {
+                    exports.count += 1;
+                    // A dated export is preferred over an undated one.
+                    let earlier = match (export.created_at, exports.earliest.created_at) {
+                        (Some(created_at), Some(earliest)) => created_at < earliest,
+                        (Some(_), None) => true,
+                        (None, _) => false,
+                    };
+                    if earlier {
+                        exports.earliest = export;
+                    }
+                })
+                .or_insert(Exports {
+                    earliest: export,
+                    count: 1,
+                });
+        }
+
+        let transactions = group_entries(ledger);
+        let ledger_ids = ledger
+            .iter()
+            .map(|entry| entry.transaction.transaction_id.as_str())
+            .collect::<HashSet<_>>();
+
+        let mut reconciliation = Self {
+            run_id: ledger
+                .first()
+                .map(|entry| entry.run_id.clone())
+                .unwrap_or_default(),
+            unknown_exports: exports
+                .keys()
+                .filter(|id| !ledger_ids.contains(*id))
+                .count() as u64,
+            ..Self::default()
+        };
+
+        for entries in &transactions {
+            let entry = entries
+                .iter()
+                .find(|entry| entry.expectation.should_match)
+                .unwrap_or(&entries[0]);
+            let published_at = entries
+                .iter()
+                .map(|entry| entry.published_at)
+                .min()
+                .unwrap_or(entry.published_at);
+            let outcome = outcome(
+                entry,
+                published_at,
+                exports.get(entry.transaction.transaction_id.as_str()),
+            );
+
+            let transaction = &entry.transaction;
+            let kind = entry.expectation.injection.map_or_else(
+                || transaction.transaction_type.to_string(),
+                |injection| injection.to_string(),
+            );
+            for tally in [
+                &mut reconciliation.total,
+                tally(&mut reconciliation.by_retailer, &transaction.merchant_name),
+                tally(
+                    &mut reconciliation.by_payment_provider,
+                    &transaction.payment_provider,
+                ),
+                tally(
+                    &mut reconciliation.by_identifier_type,
+                    &transaction.identifier_type,
+                ),
+                tally(&mut reconciliation.by_kind, &kind),
+            ] {
+                tally.record(&outcome, entry.expectation.should_match);
+            }
+        }
+
+        reconciliation
+    }
+}
+
+/// The exports Harmonia made for one transaction ID.
+struct Exports<'a> {
+    earliest: &'a ExportedTransaction,
+    count: usize,
+}
+
+/// Groups an auth with its settlement, and leaves every other entry on its own. An injected
+/// duplicate of an auth is grouped with its own settlement, not with the auth it copies.
+fn group_entries(ledger: &[LedgerEntry]) -> Vec<Vec<&LedgerEntry>> {
+    let mut transactions: Vec<Vec<&LedgerEntry>> = Vec::new();
+    let mut lifecycles: HashMap<(&str, bool), usize> = HashMap::new();
+    for entry in ledger {
+        if !matches!(
+            entry.transaction.transaction_type,
+            TransactionType::Auth | TransactionType::Settlement
+        ) {
+            transactions.push(vec![entry]);
+            continue;
+        }
+
+        let duplicate = entry.expectation.injection == Some(Injection::DuplicateTransactionId);
+        let key = (entry.transaction.transaction_id.as_str(), duplicate);
+        if let Some(&index) = lifecycles.get(&key) {
+            transactions[index].push(entry);
+        } else {
+            lifecycles.insert(key, transactions.len());
+            transactions.push(vec![entry]);
+        }
+    }
+    transactions
+}
+
+fn outcome(entry: &LedgerEntry, published_at: DateTime<Utc>, exports: Option<&Exports>) -> Outcome {
+    if entry.expectation.injection == Some(Injection::DuplicateTransactionId) {
+        // The transaction whose ID was reused accounts for one export, so any more were not
+        // deduplicated.
+        return if exports.is_some_and(|exports| exports.count > 1) {
+            Outcome::DuplicateExported
+        } else {
+            Outcome::Deduplicated
+        };
+    }
+
+    match exports.map(|exports| exports.earliest) {
+        Some(export)
+            if !entry.expectation.should_match
+                || export
+                    .provider_slug
+                    .as_ref()
+                    .is_some_and(|slug| slug != &entry.transaction.merchant_name) =>
+        {
+            Outcome::FalseMatch
+        }
+        // Without an export date there is no latency to record.
+        Some(export) => Outcome::Matched {
+            latency_ms: export.created_at.map(|created_at| {
+                u64::try_from((created_at - published_at).num_milliseconds()).unwrap_or(0)
+            }),
+        },
+        None if entry.expectation.should_match => Outcome::Missing,
+        None => Outcome::Rejected,
+    }
+}
+
+impl Tally {
+    fn record(&mut self, outcome: &Outcome, should_match: bool) {
+        if should_match {
+            self.expected += 1;
+        }
+        match outcome {
+            Outcome::Matched { latency_ms } => {
+                self.matched += 1;
+                if let Some(latency_ms) = latency_ms {
+                    // The histogram resizes to fit, where saturating_record would clamp.
+                    let _ = self.latencies.record(*latency_ms);
+                }
+            }
+            Outcome::Missing => self.missing += 1,
+            Outcome::FalseMatch => self.false_matches += 1,
+            Outcome::Rejected => {}
+            Outcome::Deduplicated => self.duplicates += 1,
+            Outcome::DuplicateExported => {
+                self.duplicates += 1;
+                self.duplicates_exported += 1;
+            }
+        }
+    }
+}
+
+fn tally<'a>(tallies: &'a mut BTreeMap<String, Tally>, key: &str) -> &'a mut Tally {
+    if !tallies.contains_key(key) {
+        tallies.insert(key.to_owned(), Tally::default());
+    }
+    tallies.get_mut(key).expect("the tally was just inserted")
+}
+
+impl fmt::Display for Tally {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "expected {}, matched {} ({:.2}%), missing {}, false matches {}",
+            self.expected,
+            self.matched,
+            self.match_rate() * 100.0,
+            self.missing,
+            self.false_matches
+        )?;
+        if self.duplicates > 0 {
+            write!(
+                f,
+                ", duplicates {} ({} exported again)",
+                self.duplicates, self.duplicates_exported
+            )?;
+        }
+        if !self.latencies.is_empty() {
+            let percentiles = LATENCY_PERCENTILES
+                .iter()
+                .map(|percentile| {
+                    format!(
+                        "p{percentile} {}ms",
+                        self.latencies.value_at_percentile(*percentile)
+                    )
+                })
+                .collect::<Vec<_>>();
+            write!(f, ", latency {}", percentiles.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Reconciliation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "run {}", self.run_id)?;
+        writeln!(f, "total: {}", self.total)?;
+        writeln!(
+            f,
+            "exported transactions not in the ledger: {}",
+            self.unknown_exports
+        )?;
+        for (title, tallies) in [
+            ("retailer", &self.by_retailer),
+            ("payment provider", &self.by_payment_provider),
+            ("identifier type", &self.by_identifier_type),
+            ("kind", &self.by_kind),
+        ] {
+            writeln!(f, "\nper {title}:")?;
+            for (key, tally) in tallies {
+                writeln!(f, "  {key}: {tally}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::models::{Transaction, TransactionType};
+    use crate::transactor::injection::Injection;
+    use crate::transactor::ledger::Expectation;
+
+    fn entry(
+        transaction_id: &str,
+        payment_provider: &str,
+        published_at: DateTime<Utc>,
+        injection: Option<Injection>,
+    ) -> LedgerEntry {
+        LedgerEntry {
+            run_id: "run_1".to_owned(),
+            published_at,
+            routing_key: format!("transactions.{payment_provider}.test_slug"),
+            expectation: Expectation::new(true, true, injection),
+            transaction: Transaction {
+                transaction_date: published_at,
+                payment_provider: payment_provider.to_owned(),
+                merchant_name: "test_slug".to_owned(),
+                transaction_id: transaction_id.to_owned(),
+                identifier: "mid_1".to_owned(),
+                token: "token_1".to_owned(),
+                ..Transaction::fixture()
+            },
+        }
+    }
+
+    fn export(transaction_id: &str, created_at: DateTime<Utc>) -> ExportedTransaction {
+        ExportedTransaction {
+            transaction_id: transaction_id.to_owned(),
+            provider_slug: Some("test_slug".to_owned()),
+            created_at: Some(created_at),
+        }
+    }
+
+    #[test]
+    fn exports_are_reconciled_against_the_ledger() {
+        let published_at = Utc::now();
+        let ledger = [
+            entry("tx_1", "visa", published_at, None),
+            entry("tx_2", "visa", published_at, None),
+            entry("tx_3", "amex", published_at, Some(Injection::UnknownMid)),
+            entry("tx_4", "amex", published_at, Some(Injection::ZeroAmount)),
+        ];
+        let exported = [
+            export("tx_1", published_at + Duration::milliseconds(1500)),
+            export("tx_3", published_at),
+            export("tx_9", published_at),
+        ];
+
+        let reconciliation = Reconciliation::new(&ledger, &exported);
+
+        assert_eq!(reconciliation.run_id, "run_1");
+        assert_eq!(reconciliation.total.expected, 2);
+        assert_eq!(reconciliation.total.matched, 1);
+        assert_eq!(reconciliation.total.missing, 1);
+        assert_eq!(reconciliation.total.false_matches, 1);
+        assert_eq!(reconciliation.unknown_exports, 1);
+        assert!((reconciliation.total.match_rate() - 0.5).abs() < f64::EPSILON);
+        assert_eq!(
+            reconciliation.total.latencies.value_at_percentile(100.0),
+            1500
+        );
+
+        let visa = &reconciliation.by_payment_provider["visa"];
+        assert_eq!((visa.expected, visa.matched, visa.missing), (2, 1, 1));
+        let amex = &reconciliation.by_payment_provider["amex"];
+        assert_eq!((amex.expected, amex.false_matches), (0, 1));
+        assert_eq!(reconciliation.by_identifier_type["PRIMARY"].expected, 2);
+        assert_eq!(reconciliation.by_kind["purchase"].expected, 2);
+        assert_eq!(reconciliation.by_kind["unknown_mid"].false_matches, 1);
+    }
+
+    #[test]
+    fn refunds_are_told_apart_from_negative_amounts() {
+        let published_at = Utc::now();
+        let mut refund = entry("tx_2", "visa", published_at, None);
+        refund.transaction.amount = -245;
+        refund.transaction.transaction_type = TransactionType::Refund;
+        refund.transaction.original_transaction_id = Some("tx_1".to_owned());
+        let mut negative = entry(
+            "tx_3",
+            "visa",
+            published_at,
+            Some(Injection::NegativeAmount),
+        );
+        negative.transaction.amount = -245;
+
+        let reconciliation = Reconciliation::new(&[refund, negative], &[]);
+
+        assert_eq!(reconciliation.by_kind["refund"].missing, 1);
+        assert_eq!(reconciliation.by_kind["negative_amount"].expected, 0);
+        assert_eq!(reconciliation.by_kind["negative_amount"].missing, 0);
+    }
+
+    #[test]
+    fn auths_and_settlements_count_once() {
+        let published_at = Utc::now();
+        let mut auth = entry("tx_1", "visa", published_at, None);
+        auth.transaction.transaction_type = TransactionType::Auth;
+        let mut settlement = entry("tx_1", "visa", published_at + Duration::seconds(10), None);
+        settlement.transaction.transaction_type = TransactionType::Settlement;
+        let ledger = [auth, settlement];
+
+        let reconciliation = Reconciliation::new(
+            &ledger,
+            &[export("tx_1", published_at + Duration::seconds(11))],
+        );
+
+        assert_eq!(reconciliation.total.expected, 1);
+        assert_eq!(reconciliation.total.matched, 1);
+        let latencies = &reconciliation.total.latencies;
+        assert!(latencies.equivalent(latencies.value_at_percentile(100.0), 11000));
+    }
+
+    #[test]
+    fn injected_duplicates_are_checked_apart_from_their_original() {
+        let published_at = Utc::now();
+        let ledger = [
+            entry("tx_1", "visa", published_at, None),
+            entry(
+                "tx_1",
+                "visa",
+                published_at,
+                Some(Injection::DuplicateTransactionId),
+            ),
+            entry("tx_2", "visa", published_at, None),
+            entry(
+                "tx_2",
+                "visa",
+                published_at,
+                Some(Injection::DuplicateTransactionId),
+            ),
+        ];
+        let exported = [
+            export("tx_1", published_at),
+            export("tx_2", published_at),
+            export("tx_2", published_at),
+        ];
+
+        let reconciliation = Reconciliation::new(&ledger, &exported);
+
+        assert_eq!(reconciliation.total.expected, 2);
+        assert_eq!(reconciliation.total.matched, 2);
+        assert_eq!(reconciliation.total.false_matches, 0);
+        assert_eq!(reconciliation.total.duplicates, 2);
+        assert_eq!(reconciliation.total.duplicates_exported, 1);
+    }
+
+    #[test]
+    fn undated_exports_have_no_latency() {
+        let published_at = Utc::now();
+        let exported = [ExportedTransaction {
+            created_at: None,
+            ..export("tx_1", published_at)
+        }];
+
+        let reconciliation =
+            Reconciliation::new(&[entry("tx_1", "visa", published_at, None)], &exported);
+
+        assert_eq!(reconciliation.total.matched, 1);
+        assert!(reconciliation.total.latencies.is_empty());
+    }
+
+    #[test]
+    fn dated_exports_are_preferred_for_latency() {
+        let published_at = Utc::now();
+        let exported = [
+            export("tx_1", published_at + Duration::milliseconds(100)),
+            ExportedTransaction {
+                created_at: None,
+                ..export("tx_1", published_at)
+            },
+        ];
+
+        let reconciliation =
+            Reconciliation::new(&[entry("tx_1", "visa", published_at, None)], &exported);
+
+        assert_eq!(reconciliation.total.matched, 1);
+        assert_eq!(reconciliation.total.latencies.len(), 1);
+        assert_eq!(
+            reconciliation.total.latencies.value_at_percentile(100.0),
+            100
+        );
+    }
+}
@@ -97,7 +97,7 @@ impl super::Sender for Sender {
         {
             Ok(resp) => info!("response status: {}", resp.status()),
             Err(e) => error!("connection error, transaction discarded: {e}"),
-        };
+        }
 
         Ok(())
     }
@@ -0,0 +1,173 @@
+use color_eyre::{eyre::eyre, Result};
+use rand::distributions::{Distribution, Uniform, WeightedIndex};
+use rand::Rng;
+use rand_distr::{LogNormal, Normal};
+
+use crate::models::AmountDistribution;
+
+/// Draws transaction amounts, in minor units, from a configured distribution.
+pub enum AmountSampler {
+    Uniform(Uniform<i64>),
+    Normal {
+        dist: Normal<f64>,
+        min: i64,
+        max: i64,
+    },
+    LogNormal {
+        dist: LogNormal<f64>,
+        min: i64,
+        max: i64,
+    },
+    Fixed(Vec<i64>),
+    Histogram {
+        buckets: Vec<Uniform<i64>>,
+        weights: WeightedIndex<u32>,
+    },
+}
+
+impl AmountSampler {
+    /// Builds a sampler for the given distribution.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the distribution's parameters are invalid, such as an empty range.
+    pub fn from_config(config: &AmountDistribution) -> Result<Self> {
+        let bounds = |min: Option<i64>, max: Option<i64>| -> Result<(i64, i64)> {
+            let (min, max) = (min.unwrap_or(1), max.unwrap_or(i64::MAX));
+            if min > max {
+                Err(eyre!("amount min {min} is greater than max {max}"))
+            } else {
+                Ok((min, max))
+            }
+        };
+
+        Ok(match config {
+            AmountDistribution::Uniform { min, max } => {
+                if min >= max {
+                    return Err(eyre!("uniform amount range {min}..{max} is empty"));
+                }
+                Self::Uniform(Uniform::new(*min, *max))
+            }
+            AmountDistribution::Normal {
+                mean,
+                std_dev,
+                min,
+                max,
+            } => {
+                let (min, max) = bounds(*min, *max)?;
+                Self::Normal {
+                    dist: Normal::new(*mean, *std_dev)
+                        .map_err(|e| eyre!("invalid normal amount distribution: {e}"))?,
+                    min,
+                    max,
+                }
+            }
+            AmountDistribution::LogNormal {
+                median,
+                sigma,
+                min,
+                max,
+            } => {
+                if *median <= 0.0 {
+                    return Err(eyre!("log-normal amount median must be positive"));
+                }
+                let (min, max) = bounds(*min, *max)?;
+                Self::LogNormal {
+                    dist: LogNormal::new(median.ln(), *sigma)
+                        .map_err(|e| eyre!("invalid log-normal amount distribution: {e}"))?,
+                    min,
+                    max,
+                }
+            }
+            AmountDistribution::Fixed(amounts) => {
+                if amounts.is_empty() {
+                    return Err(eyre!("fixed amount list is empty"));
+                }
+                Self::Fixed(amounts.clone())
+            }
+            AmountDistribution::Histogram(buckets) => {
+                if let Some((from, to, _)) = buckets.iter().find(|(from, to, _)| from >= to) {
+                    return Err(eyre!("amount histogram bucket {from}..{to} is empty"));
+                }
+                Self::Histogram {
+                    buckets: buckets
+                        .iter()
+                        .map(|(from, to, _)| Uniform::new(*from, *to))
+                        .collect(),
+                    weights: WeightedIndex::new(buckets.iter().map(|(_, _, weight)| *weight))
+                        .map_err(|e| eyre!("invalid amount histogram weights: {e}"))?,
+                }
+            }
+        })
+    }
+
+    pub fn sample(&self, rng: &mut impl Rng) -> i64 {
+        match self {
+            Self::Uniform(dist) => dist.sample(rng),
+            Self::Normal { dist, min, max } => (dist.sample(rng).round() as i64).clamp(*min, *max),
+            Self::LogNormal { dist, min, max } => {
+                (dist.sample(rng).round() as i64).clamp(*min, *max)
+            }
+            Self::Fixed(amounts) => amounts[rng.gen_range(0..amounts.len())],
+            Self::Histogram { buckets, weights } => buckets[weights.sample(rng)].sample(rng),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn samples(config: &AmountDistribution) -> Result<Vec<i64>> {
+        let sampler = AmountSampler::from_config(config)?;
+        let mut rng = StdRng::seed_from_u64(1);
+        Ok((0..1000).map(|_| sampler.sample(&mut rng)).collect())
+    }
+
+    #[test]
+    fn log_normal_amounts_are_clamped() -> Result<()> {
+        let amounts = samples(&AmountDistribution::LogNormal {
+            median: 800.0,
+            sigma: 2.0,
+            min: Some(100),
+            max: Some(5000),
+        })?;
+
+        assert!(amounts.iter().all(|amount| (100..=5000).contains(amount)));
+        assert!(amounts.contains(&100));
+        assert!(amounts.contains(&5000));
+
+        Ok(())
+    }
+
+    #[test]
+    fn fixed_amounts_come_from_the_list() -> Result<()> {
+        let amounts = samples(&AmountDistribution::Fixed(vec![250, 500]))?;
+        assert!(amounts.iter().all(|amount| [250, 500].contains(amount)));
+        Ok(())
+    }
+
+    #[test]
+    fn histogram_amounts_respect_weights() -> Result<()> {
+        let amounts = samples(&AmountDistribution::Histogram(vec![
+            (100, 200, 1),
+            (1000, 2000, 0),
+            (5000, 6000, 3),
+        ]))?;
+
+        assert!(amounts.iter().all(|amount| !(200..5000).contains(amount)));
+        let high = amounts.iter().filter(|amount| **amount >= 5000).count();
+        assert!(high > 600);
+
+        Ok(())
+    }
+
+    #[test]
+    fn empty_uniform_range_is_rejected() {
+        let config = AmountDistribution::Uniform { min: 100, max: 100 };
+        assert!(AmountSampler::from_config(&config).is_err());
+    }
+}
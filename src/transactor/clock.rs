@@ -0,0 +1,128 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use super::profile::LoadProfile;
+
+/// The step a simulated clock takes while the load profile asks for no transactions.
+const IDLE_STEP: Duration = Duration::from_millis(100);
+
+/// The most idle steps a single tick takes, so a long quiet phase cannot hold up generation.
+const MAX_IDLE_STEPS: u32 = 36_000;
+
+/// Supplies transaction dates.
+///
+/// The wall clock stamps transactions as they are generated. A simulated clock starts at a fixed
+/// date and advances by the load profile's inter-arrival time, so seeded runs are reproducible.
+pub enum Clock {
+    Wall,
+    Simulated {
+        start: DateTime<Utc>,
+        elapsed: Duration,
+    },
+}
+
+impl Clock {
+    #[must_use]
+    pub const fn simulated(start: DateTime<Utc>) -> Self {
+        Self::Simulated {
+            start,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    /// Returns the date for the next transaction and moves the clock on.
+    pub fn tick(&mut self, profile: &LoadProfile) -> DateTime<Utc> {
+        match self {
+            Self::Wall => Utc::now(),
+            Self::Simulated { start, elapsed } => {
+                let now = *start + *elapsed;
+                for _ in 0..MAX_IDLE_STEPS {
+                    match profile.target(*elapsed) {
+                        Some(target) if target.rate > 0.0 => {
+                            *elapsed += Duration::from_secs_f64(1.0 / target.rate);
+                            break;
+                        }
+                        // A phase with no rate and no end would never move the clock on.
+                        Some(target) if profile.phase_ends(target.phase) => {
+                            *elapsed += IDLE_STEP;
+                        }
+                        Some(_) | None => break,
+                    }
+                }
+                now
+            }
+        }
+    }
+
+    /// Starts following a new load profile from where the clock has got to.
+    pub fn restart(&mut self) {
+        if let Self::Simulated { start, elapsed } = self {
+            *start += *elapsed;
+            *elapsed = Duration::ZERO;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use color_eyre::Result;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::models::{LoadPhase, LoadShape};
+
+    #[test]
+    fn simulated_clock_follows_rate() {
+        let start = Utc
+            .with_ymd_and_hms(2024, 1, 1, 12, 0, 0)
+            .single()
+            .expect("valid date");
+        let profile = LoadProfile::constant(4.0);
+        let mut clock = Clock::simulated(start);
+
+        let dates = (0..3).map(|_| clock.tick(&profile)).collect::<Vec<_>>();
+        assert_eq!(
+            dates,
+            vec![
+                start,
+                start + chrono::Duration::milliseconds(250),
+                start + chrono::Duration::milliseconds(500),
+            ]
+        );
+    }
+
+    #[test]
+    fn simulated_clock_stands_still_at_zero_rate() {
+        let start = Utc
+            .with_ymd_and_hms(2024, 1, 1, 12, 0, 0)
+            .single()
+            .expect("valid date");
+        let profile = LoadProfile::constant(0.0);
+        let mut clock = Clock::simulated(start);
+
+        assert_eq!(clock.tick(&profile), start);
+        assert_eq!(clock.tick(&profile), start);
+    }
+
+    #[test]
+    fn simulated_clock_moves_on_through_a_quiet_phase() -> Result<()> {
+        let start = Utc
+            .with_ymd_and_hms(2024, 1, 1, 12, 0, 0)
+            .single()
+            .expect("valid date");
+        let profile = LoadProfile::from_phases(&[LoadPhase {
+            name: None,
+            shape: LoadShape::Soak {
+                rate: 0.0,
+                duration_secs: u64::MAX,
+            },
+        }])?;
+        let mut clock = Clock::simulated(start);
+
+        assert_eq!(clock.tick(&profile), start);
+        assert_eq!(clock.tick(&profile), start + chrono::Duration::hours(1));
+        Ok(())
+    }
+}
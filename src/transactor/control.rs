@@ -0,0 +1,391 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post, put};
+use axum::{Json, Router};
+use color_eyre::{eyre::eyre, Result};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use super::profile::LoadProfile;
+use super::server;
+use crate::models::{ControlConfig, LoadPhase};
+
+/// Checks a retailer's new payment provider percentages before they are accepted.
+type PercentageCheck = Box<dyn Fn(&str, &[(String, u32)]) -> Result<()> + Send + Sync>;
+
+/// Changes to a run asked for through the control API. The producer picks them up between bursts.
+pub struct Control {
+    retailer_slugs: HashSet<String>,
+    percentage_check: Option<PercentageCheck>,
+    state: Mutex<ControlState>,
+}
+
+#[derive(Default)]
+struct ControlState {
+    paused: bool,
+    profile: Option<LoadProfile>,
+    percentages: HashMap<String, Vec<(String, u32)>>,
+    burst: u64,
+}
+
+/// The state of the run, as returned by every control API call.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct Status {
+    pub paused: bool,
+    /// Burst transactions not yet generated.
+    pub pending_burst: u64,
+}
+
+impl Control {
+    /// Creates the control for a run of the given retailers, which is not paused.
+    pub fn new<S: Into<String>>(retailer_slugs: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            retailer_slugs: retailer_slugs.into_iter().map(Into::into).collect(),
+            percentage_check: None,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Checks new percentages with `check`, given the retailer's slug, so that percentages the
+    /// run cannot serve are refused rather than accepted and dropped later.
+    #[must_use]
+    pub fn with_percentage_check(
+        mut self,
+        check: impl Fn(&str, &[(String, u32)]) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.percentage_check = Some(Box::new(check));
+        self
+    }
+
+    fn state(&self) -> MutexGuard<'_, ControlState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    #[must_use]
+    pub fn status(&self) -> Status {
+        let state = self.state();
+        Status {
+            paused: state.paused,
+            pending_burst: state.burst,
+        }
+    }
+
+    pub fn pause(&self) {
+        self.state().paused = true;
+        info!("transactor paused");
+    }
+
+    pub fn resume(&self) {
+        self.state().paused = false;
+        info!("transactor resumed");
+    }
+
+    #[must_use]
+    pub fn is_paused(&self) -> bool {
+        self.state().paused
+    }
+
+    /// Replaces the load profile with a constant rate that runs until the transactor is stopped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the rate is negative or not a number.
+    pub fn set_rate(&self, rate: f64) -> Result<()> {
+        if !rate.is_finite() || rate < 0.0 {
+            return Err(eyre!("invalid rate {rate}"));
+        }
+        self.set_profile(LoadProfile::constant(rate));
+        Ok(())
+    }
+
+    /// Replaces the load profile, which starts again from its first phase.
+    pub fn set_profile(&self, profile: LoadProfile) {
+        self.state().profile = Some(profile);
+        info!("load profile replaced");
+    }
+
+    /// Returns the profile to switch to, if it has changed since last asked.
+    #[must_use]
+    pub fn take_profile(&self) -> Option<LoadProfile> {
+        self.state().profile.take()
+    }
+
+    /// Replaces a retailer's payment provider percentages.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the retailer is not part of the run, no provider has a weight, or the
+    /// percentage check fails.
+    pub fn set_percentage(
+        &self,
+        retailer_slug: &str,
+        percentage: Vec<(String, u32)>,
+    ) -> Result<()> {
+        if !self.retailer_slugs.contains(retailer_slug) {
+            return Err(eyre!("retailer {retailer_slug} is not configured"));
+        }
+        if percentage.iter().all(|(_, weight)| *weight == 0) {
+            return Err(eyre!("no payment provider has a weight"));
+        }
+        if let Some(check) = &self.percentage_check {
+            check(retailer_slug, &percentage)?;
+        }
+        info!(
+            retailer_slug,
+            ?percentage,
+            "payment provider percentages replaced"
+        );
+        self.state()
+            .percentages
+            .insert(retailer_slug.to_owned(), percentage);
+        Ok(())
+    }
+
+    /// Returns the percentages changed since last asked, by retailer.
+    #[must_use]
+    pub fn take_percentages(&self) -> HashMap<String, Vec<(String, u32)>> {
+        std::mem::take(&mut self.state().percentages)
+    }
+
+    /// Asks for `count` transactions on top of the load profile, sent as fast as they can be.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `count` is zero.
+    pub fn add_burst(&self, count: u64) -> Result<()> {
+        if count == 0 {
+            return Err(eyre!("a burst needs at least one transaction"));
+        }
+        let mut state = self.state();
+        state.burst = state.burst.saturating_add(count);
+        info!(count, pending = state.burst, "burst requested");
+        Ok(())
+    }
+
+    /// Takes up to `max` of the pending burst transactions.
+    #[must_use]
+    pub fn take_burst(&self, max: u64) -> u64 {
+        let mut state = self.state();
+        let count = state.burst.min(max);
+        state.burst -= count;
+        count
+    }
+}
+
+/// Serves the control API from a background thread for as long as the transactor runs.
+///
+/// # Errors
+///
+/// Returns an error if the address cannot be bound.
+pub fn serve(config: &ControlConfig, control: Arc<Control>) -> Result<()> {
+    server::spawn("control", config.address, router(control))
+}
+
+fn router(control: Arc<Control>) -> Router {
+    Router::new()
+        .route("/status", get(status))
+        .route("/pause", post(pause))
+        .route("/resume", post(resume))
+        .route("/rate", put(set_rate))
+        .route("/profile", put(set_profile))
+        .route("/retailers/:retailer_slug/percentage", put(set_percentage))
+        .route("/burst", post(burst))
+        .with_state(control)
+}
+
+type StatusResponse = Result<Json<Status>, (StatusCode, String)>;
+
+fn respond(control: &Control, result: Result<()>) -> StatusResponse {
+    result
+        .map(|()| Json(control.status()))
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))
+}
+
+async fn status(State(control): State<Arc<Control>>) -> Json<Status> {
+    Json(control.status())
+}
+
+async fn pause(State(control): State<Arc<Control>>) -> Json<Status> {
+    control.pause();
+    Json(control.status())
+}
+
+async fn resume(State(control): State<Arc<Control>>) -> Json<Status> {
+    control.resume();
+    Json(control.status())
+}
+
+#[derive(Deserialize)]
+struct RateRequest {
+    transactions_per_second: f64,
+}
+
+async fn set_rate(
+    State(control): State<Arc<Control>>,
+    Json(request): Json<RateRequest>,
+) -> StatusResponse {
+    respond(&control, control.set_rate(request.transactions_per_second))
+}
+
+/// The same phases as the transactor config's `[[phases]]`.
+#[derive(Deserialize)]
+struct ProfileRequest {
+    phases: Vec<LoadPhase>,
+}
+
+async fn set_profile(
+    State(control): State<Arc<Control>>,
+    Json(request): Json<ProfileRequest>,
+) -> StatusResponse {
+    let profile = LoadProfile::from_phases(&request.phases);
+    respond(
+        &control,
+        profile.map(|profile| control.set_profile(profile)),
+    )
+}
+
+/// The same `[[provider, weight], ...]` pairs as a retailer's `percentage` config.
+#[derive(Deserialize)]
+struct PercentageRequest {
+    percentage: Vec<(String, u32)>,
+}
+
+async fn set_percentage(
+    State(control): State<Arc<Control>>,
+    Path(retailer_slug): Path<String>,
+    Json(request): Json<PercentageRequest>,
+) -> StatusResponse {
+    respond(
+        &control,
+        control.set_percentage(&retailer_slug, request.percentage),
+    )
+}
+
+#[derive(Deserialize)]
+struct BurstRequest {
+    count: u64,
+}
+
+async fn burst(
+    State(control): State<Arc<Control>>,
+    Json(request): Json<BurstRequest>,
+) -> StatusResponse {
+    respond(&control, control.add_burst(request.count))
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::transactor::profile::Target;
+
+    #[test]
+    fn bursts_are_taken_in_chunks() -> Result<()> {
+        let control = Control::new(["costa"]);
+        control.add_burst(2500)?;
+        control.add_burst(500)?;
+
+        assert_eq!(control.take_burst(1000), 1000);
+        assert_eq!(
+            control.status(),
+            Status {
+                paused: false,
+                pending_burst: 2000
+            }
+        );
+        assert_eq!(control.take_burst(1000), 1000);
+        assert_eq!(control.take_burst(1000), 1000);
+        assert_eq!(control.take_burst(1000), 0);
+        assert!(control.add_burst(0).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn changes_are_taken_once() -> Result<()> {
+        let control = Control::new(["costa", "wasabi-club"]);
+        control.pause();
+        assert!(control.is_paused());
+        control.resume();
+        assert!(!control.is_paused());
+
+        control.set_rate(250.0)?;
+        let profile = control.take_profile();
+        assert_eq!(
+            profile.and_then(|profile| profile.target(std::time::Duration::ZERO)),
+            Some(Target {
+                phase: 0,
+                rate: 250.0
+            })
+        );
+        assert!(control.take_profile().is_none());
+        assert!(control.set_rate(-1.0).is_err());
+
+        control.set_percentage("costa", vec![("visa".to_owned(), 100)])?;
+        assert_eq!(
+            control.take_percentages(),
+            HashMap::from([("costa".to_owned(), vec![("visa".to_owned(), 100)])])
+        );
+        assert!(control.take_percentages().is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn invalid_percentages_are_rejected() {
+        let control = Arc::new(Control::new(["costa"]).with_percentage_check(
+            |slug, percentage| {
+                if percentage.iter().any(|(provider, _)| provider == "amex") {
+                    return Err(eyre!("retailer {slug} has no amex tokens"));
+                }
+                Ok(())
+            },
+        ));
+        let unknown = set_percentage(
+            State(Arc::clone(&control)),
+            Path("iceland-bonus-card".to_owned()),
+            Json(PercentageRequest {
+                percentage: vec![("visa".to_owned(), 100)],
+            }),
+        )
+        .await;
+        let unweighted = set_percentage(
+            State(Arc::clone(&control)),
+            Path("costa".to_owned()),
+            Json(PercentageRequest {
+                percentage: vec![("visa".to_owned(), 0)],
+            }),
+        )
+        .await;
+
+        assert_eq!(
+            unknown.err(),
+            Some((
+                StatusCode::BAD_REQUEST,
+                "retailer iceland-bonus-card is not configured".to_owned()
+            ))
+        );
+        let unservable = set_percentage(
+            State(Arc::clone(&control)),
+            Path("costa".to_owned()),
+            Json(PercentageRequest {
+                percentage: vec![("amex".to_owned(), 100)],
+            }),
+        )
+        .await;
+
+        assert!(unweighted.is_err());
+        assert_eq!(
+            unservable.err(),
+            Some((
+                StatusCode::BAD_REQUEST,
+                "retailer costa has no amex tokens".to_owned()
+            ))
+        );
+        assert!(control.take_percentages().is_empty());
+    }
+}
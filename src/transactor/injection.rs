@@ -0,0 +1,343 @@
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+
+use color_eyre::{eyre::eyre, Result};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use uuid::Builder;
+
+use crate::models::{InjectionConfig, Transaction, TransactionType};
+
+/// How many recent transaction IDs are kept to be reused by duplicate transactions.
+const RECENT_TRANSACTION_IDS: usize = 1000;
+
+/// How many times an unknown identifier is redrawn before giving up on looking like a real one.
+const UNKNOWN_IDENTIFIER_ATTEMPTS: usize = 100;
+
+/// A fault deliberately injected into a transaction, so that it should not match.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Injection {
+    UnknownToken,
+    UnknownMid,
+    DuplicateTransactionId,
+    ZeroAmount,
+    NegativeAmount,
+    MismatchedCard,
+}
+
+impl fmt::Display for Injection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownToken => write!(f, "unknown_token"),
+            Self::UnknownMid => write!(f, "unknown_mid"),
+            Self::DuplicateTransactionId => write!(f, "duplicate_transaction_id"),
+            Self::ZeroAmount => write!(f, "zero_amount"),
+            Self::NegativeAmount => write!(f, "negative_amount"),
+            Self::MismatchedCard => write!(f, "mismatched_card"),
+        }
+    }
+}
+
+/// Spoils a configured fraction of transactions, and reports which fault each one was given.
+pub struct Injector {
+    rates: Vec<(Injection, f64)>,
+    recent_transaction_ids: VecDeque<String>,
+    known_identifiers: HashSet<String>,
+}
+
+impl Injector {
+    /// # Errors
+    ///
+    /// Returns an error if a rate is not a fraction or the rates add up to more than 1.
+    pub fn from_config(config: &InjectionConfig) -> Result<Self> {
+        let rates = vec![
+            (Injection::UnknownToken, config.unknown_token),
+            (Injection::UnknownMid, config.unknown_mid),
+            (
+                Injection::DuplicateTransactionId,
+                config.duplicate_transaction_id,
+            ),
+            (Injection::ZeroAmount, config.zero_amount),
+            (Injection::NegativeAmount, config.negative_amount),
+            (Injection::MismatchedCard, config.mismatched_card),
+        ];
+
+        if let Some((injection, rate)) = rates.iter().find(|(_, rate)| !(0.0..=1.0).contains(rate))
+        {
+            return Err(eyre!(
+                "{injection} injection rate {rate} is not between 0 and 1"
+            ));
+        }
+        let total = rates.iter().map(|(_, rate)| rate).sum::<f64>();
+        if total > 1.0 {
+            return Err(eyre!(
+                "injection rates add up to {total}, which is more than 1"
+            ));
+        }
+
+        Ok(Self {
+            rates: rates.into_iter().filter(|(_, rate)| *rate > 0.0).collect(),
+            recent_transaction_ids: VecDeque::new(),
+            known_identifiers: HashSet::new(),
+        })
+    }
+
+    /// Sets the identifiers an unknown MID must not be, which should be every identifier in the
+    /// MIDs file rather than just the retailer's own.
+    #[must_use]
+    pub fn with_known_identifiers(mut self, known_identifiers: HashSet<String>) -> Self {
+        self.known_identifiers = known_identifiers;
+        self
+    }
+
+    /// Possibly injects a fault into a transaction, returning the fault if it did.
+    pub fn inject(
+        &mut self,
+        transaction: &mut Transaction,
+        rng: &mut impl Rng,
+    ) -> Option<Injection> {
+        // Without any rates the generator is left untouched, so seeded runs are unchanged.
+        if self.rates.is_empty() {
+            return None;
+        }
+
+        let mut draw = rng.gen::<f64>();
+        let injection = self.rates.iter().find_map(|(injection, rate)| {
+            if draw < *rate {
+                Some(*injection)
+            } else {
+                draw -= rate;
+                None
+            }
+        });
+        // The first transactions have nothing to duplicate, so they are left alone. Only purchases
+        // are made negative, as a negative refund or void is not a fault.
+        let injection = match injection {
+            Some(Injection::DuplicateTransactionId) if self.recent_transaction_ids.is_empty() => {
+                None
+            }
+            Some(Injection::NegativeAmount)
+                if transaction.transaction_type != TransactionType::Purchase =>
+            {
+                None
+            }
+            injection => injection,
+        };
+
+        match injection {
+            Some(Injection::UnknownToken) => {
+                transaction.token = Builder::from_random_bytes(rng.gen())
+                    .into_uuid()
+                    .to_string();
+            }
+            Some(Injection::UnknownMid) => {
+                transaction.identifier = unknown_identifier(&transaction.identifier, rng, |id| {
+                    self.known_identifiers.contains(id)
+                });
+                // The store's real identifiers would let the transaction match after all.
+                transaction.store_identifiers = None;
+            }
+            Some(Injection::DuplicateTransactionId) => {
+                let index = rng.gen_range(0..self.recent_transaction_ids.len());
+                transaction
+                    .transaction_id
+                    .clone_from(&self.recent_transaction_ids[index]);
+            }
+            Some(Injection::ZeroAmount) => transaction.amount = 0,
+            Some(Injection::NegativeAmount) => {
+                transaction.amount = -transaction.amount.abs().max(1);
+            }
+            Some(Injection::MismatchedCard) => {
+                transaction.first_six = different_digits(&transaction.first_six, 6, rng);
+                transaction.last_four = different_digits(&transaction.last_four, 4, rng);
+            }
+            None => {}
+        }
+
+        if injection != Some(Injection::DuplicateTransactionId) {
+            if self.recent_transaction_ids.len() == RECENT_TRANSACTION_IDS {
+                self.recent_transaction_ids.pop_front();
+            }
+            self.recent_transaction_ids
+                .push_back(transaction.transaction_id.clone());
+        }
+
+        injection
+    }
+}
+
+/// Redraws the digits of an identifier until it is not a known one, so it keeps the look of a real
+/// identifier.
+fn unknown_identifier(
+    identifier: &str,
+    rng: &mut impl Rng,
+    is_known_identifier: impl Fn(&str) -> bool,
+) -> String {
+    for _ in 0..UNKNOWN_IDENTIFIER_ATTEMPTS {
+        let mut candidate = identifier
+            .chars()
+            .map(|c| {
+                if c.is_ascii_digit() {
+                    random_digit(rng)
+                } else {
+                    c
+                }
+            })
+            .collect::<String>();
+        if !identifier.chars().any(|c| c.is_ascii_digit()) {
+            candidate.extend((0..4).map(|_| random_digit(rng)));
+        }
+        if candidate != identifier && !is_known_identifier(&candidate) {
+            return candidate;
+        }
+    }
+    format!("{identifier}-unknown")
+}
+
+/// Returns `length` random digits that differ from `digits`.
+fn different_digits(digits: &str, length: usize, rng: &mut impl Rng) -> String {
+    loop {
+        let candidate = (0..length).map(|_| random_digit(rng)).collect::<String>();
+        if candidate != digits {
+            return candidate;
+        }
+    }
+}
+
+fn random_digit(rng: &mut impl Rng) -> char {
+    char::from(b'0' + rng.gen_range(0..10))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use pretty_assertions::assert_eq;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::models::{StoreIdentifiers, TransactionType};
+
+    fn transaction(transaction_id: &str) -> Transaction {
+        Transaction {
+            transaction_id: transaction_id.to_owned(),
+            identifier: "costa0001".to_owned(),
+            token: "test_token".to_owned(),
+            ..Transaction::fixture()
+        }
+    }
+
+    fn injector(config: &InjectionConfig) -> Injector {
+        Injector::from_config(config).expect("valid injection config")
+    }
+
+    #[test]
+    fn no_rates_inject_nothing() {
+        let mut injector = injector(&InjectionConfig::default());
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut tx = transaction("tx_1");
+
+        assert_eq!(injector.inject(&mut tx, &mut rng), None);
+        assert_eq!(tx.token, "test_token");
+    }
+
+    #[test]
+    fn unknown_mid_is_not_a_known_identifier() {
+        // Most identifiers that look like the retailer's belong to other retailers in the MIDs
+        // file, which must not be used either.
+        let known = (1000..10_000)
+            .map(|n| format!("costa{n:04}"))
+            .chain(std::iter::once("costa0001".to_owned()))
+            .collect::<HashSet<_>>();
+        let mut injector = injector(&InjectionConfig {
+            unknown_mid: 1.0,
+            ..InjectionConfig::default()
+        })
+        .with_known_identifiers(known.clone());
+        let mut rng = StdRng::seed_from_u64(1);
+
+        for _ in 0..100 {
+            let mut tx = transaction("tx_1");
+            tx.store_identifiers = Some(StoreIdentifiers {
+                primary: Some("costa0001".to_owned()),
+                ..StoreIdentifiers::default()
+            });
+            let injection = injector.inject(&mut tx, &mut rng);
+            assert_eq!(injection, Some(Injection::UnknownMid));
+            assert!(tx.identifier.starts_with("costa0"));
+            assert!(!known.contains(&tx.identifier));
+            assert_eq!(tx.store_identifiers, None);
+        }
+    }
+
+    #[test]
+    fn duplicates_reuse_earlier_transaction_ids() {
+        let mut injector = injector(&InjectionConfig {
+            duplicate_transaction_id: 1.0,
+            ..InjectionConfig::default()
+        });
+        let mut rng = StdRng::seed_from_u64(1);
+
+        // The first transaction has nothing to duplicate, so it is left alone and remembered.
+        let mut first = transaction("tx_1");
+        assert_eq!(injector.inject(&mut first, &mut rng), None);
+
+        let mut second = transaction("tx_2");
+        assert_eq!(
+            injector.inject(&mut second, &mut rng),
+            Some(Injection::DuplicateTransactionId)
+        );
+        assert_eq!(second.transaction_id, "tx_1");
+    }
+
+    #[test]
+    fn mismatched_card_changes_first_six_and_last_four() {
+        let mut injector = injector(&InjectionConfig {
+            mismatched_card: 1.0,
+            ..InjectionConfig::default()
+        });
+        let mut tx = transaction("tx_1");
+
+        injector.inject(&mut tx, &mut StdRng::seed_from_u64(1));
+
+        assert_eq!(tx.first_six.len(), 6);
+        assert_eq!(tx.last_four.len(), 4);
+        assert!(tx.first_six != "123456" && tx.last_four != "7890");
+    }
+
+    #[test]
+    fn only_purchases_are_made_negative() {
+        let mut injector = injector(&InjectionConfig {
+            negative_amount: 1.0,
+            ..InjectionConfig::default()
+        });
+        let mut rng = StdRng::seed_from_u64(1);
+
+        let mut purchase = transaction("tx_1");
+        assert_eq!(
+            injector.inject(&mut purchase, &mut rng),
+            Some(Injection::NegativeAmount)
+        );
+        assert_eq!(purchase.amount, -245);
+
+        let mut refund = Transaction {
+            amount: -245,
+            transaction_type: TransactionType::Refund,
+            original_transaction_id: Some("tx_1".to_owned()),
+            ..transaction("tx_2")
+        };
+        assert_eq!(injector.inject(&mut refund, &mut rng), None);
+        assert_eq!(refund.amount, -245);
+    }
+
+    #[test]
+    fn rates_over_one_are_rejected() {
+        let config = InjectionConfig {
+            zero_amount: 0.6,
+            negative_amount: 0.6,
+            ..InjectionConfig::default()
+        };
+        assert!(Injector::from_config(&config).is_err());
+    }
+}
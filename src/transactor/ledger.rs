@@ -0,0 +1,284 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use color_eyre::{eyre::eyre, Result};
+use serde::{Deserialize, Serialize};
+
+use super::injection::Injection;
+use crate::models::{LedgerConfig, Transaction};
+
+/// What Harmonia should make of a transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Expectation {
+    /// Whether the token is in the tokens file, and so known to Hermes.
+    pub token_known: bool,
+    /// Whether the identifier is in the MIDs file, and so known to Hermes.
+    pub mid_known: bool,
+    /// The fault deliberately injected into the transaction, if any.
+    pub injection: Option<Injection>,
+    pub should_match: bool,
+}
+
+impl Expectation {
+    #[must_use]
+    pub const fn new(token_known: bool, mid_known: bool, injection: Option<Injection>) -> Self {
+        Self {
+            token_known,
+            mid_known,
+            injection,
+            should_match: token_known && mid_known && injection.is_none(),
+        }
+    }
+}
+
+/// One line of the ledger: a published transaction, where it was sent and what should become of
+/// it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    pub run_id: String,
+    pub published_at: DateTime<Utc>,
+    pub routing_key: String,
+    #[serde(flatten)]
+    pub expectation: Expectation,
+    #[serde(flatten)]
+    pub transaction: Transaction,
+}
+
+/// An append-only JSONL record of every published transaction, rotated by size.
+pub struct Ledger {
+    path: PathBuf,
+    max_bytes: u64,
+    run_id: String,
+    writer: BufWriter<File>,
+    written: u64,
+}
+
+impl Ledger {
+    /// Opens the ledger for appending, rotating it first if it is already full.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the ledger cannot be opened or rotated.
+    pub fn open(config: &LedgerConfig, run_id: String) -> Result<Self> {
+        if config.max_bytes == 0 {
+            return Err(eyre!("ledger max_bytes must be more than 0"));
+        }
+
+        let (writer, written) = open_file(&config.path)?;
+        let mut ledger = Self {
+            path: config.path.clone(),
+            max_bytes: config.max_bytes,
+            run_id,
+            writer,
+            written,
+        };
+        if ledger.written >= ledger.max_bytes {
+            ledger.rotate()?;
+        }
+
+        Ok(ledger)
+    }
+
+    #[must_use]
+    pub fn run_id(&self) -> &str {
+        &self.run_id
+    }
+
+    /// Appends a transaction published just now.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the entry cannot be written, or the ledger cannot be rotated.
+    pub fn record(
+        &mut self,
+        transaction: &Transaction,
+        routing_key: &str,
+        expectation: Expectation,
+    ) -> Result<()> {
+        let entry = LedgerEntry {
+            run_id: self.run_id.clone(),
+            published_at: Utc::now(),
+            routing_key: routing_key.to_owned(),
+            expectation,
+            transaction: transaction.clone(),
+        };
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+
+        // Entries are never split across files, so a full file may run over by one entry.
+        if self.written > 0 && self.written + line.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        self.writer.write_all(&line)?;
+        self.written += line.len() as u64;
+
+        Ok(())
+    }
+
+    /// # Errors
+    ///
+    /// Returns an error if buffered entries cannot be written.
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Moves the current file aside to the first unused `<path>.<n>` and starts a new one.
+    fn rotate(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        let rotated = (1..u32::MAX)
+            .map(|n| numbered(&self.path, n))
+            .find(|path| !path.exists())
+            .ok_or_else(|| eyre!("no unused name to rotate the ledger to"))?;
+        fs::rename(&self.path, &rotated)?;
+
+        (self.writer, self.written) = open_file(&self.path)?;
+
+        Ok(())
+    }
+}
+
+/// Lists a ledger's files, oldest first: the rotated files in order, then the current one.
+#[must_use]
+pub fn ledger_files(path: &Path) -> Vec<PathBuf> {
+    let mut files = (1..u32::MAX)
+        .map(|n| numbered(path, n))
+        .take_while(|path| path.exists())
+        .collect::<Vec<_>>();
+    if path.exists() {
+        files.push(path.to_owned());
+    }
+    files
+}
+
+fn numbered(path: &Path, n: u32) -> PathBuf {
+    let mut numbered = path.as_os_str().to_owned();
+    numbered.push(format!(".{n}"));
+    PathBuf::from(numbered)
+}
+
+fn open_file(path: &Path) -> Result<(BufWriter<File>, u64)> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| eyre!("failed to open ledger {}: {e}", path.to_string_lossy()))?;
+    let written = file.metadata()?.len();
+    Ok((BufWriter::new(file), written))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader};
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn transaction() -> Transaction {
+        Transaction {
+            merchant_name: "test_slug".to_owned(),
+            transaction_id: "tx_1".to_owned(),
+            identifier: "mid_1".to_owned(),
+            token: "token_1".to_owned(),
+            ..Transaction::fixture()
+        }
+    }
+
+    fn temp_dir(name: &str) -> Result<PathBuf> {
+        let dir =
+            std::env::temp_dir().join(format!("atalanta-ledger-{name}-{}", std::process::id()));
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+        fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+
+    fn read_entries(path: &Path) -> Result<Vec<LedgerEntry>> {
+        BufReader::new(File::open(path)?)
+            .lines()
+            .map(|line| Ok(serde_json::from_str(&line?)?))
+            .collect()
+    }
+
+    #[test]
+    fn entries_record_the_expected_outcome() -> Result<()> {
+        let dir = temp_dir("entries")?;
+        let config = LedgerConfig {
+            path: dir.join("ledger.jsonl"),
+            max_bytes: 1024 * 1024,
+            run_id: None,
+        };
+
+        let mut ledger = Ledger::open(&config, "run_1".to_owned())?;
+        ledger.record(
+            &transaction(),
+            "transactions.visa.test_slug",
+            Expectation::new(true, true, None),
+        )?;
+        ledger.record(
+            &transaction(),
+            "transactions.visa.test_slug",
+            Expectation::new(false, true, Some(Injection::UnknownToken)),
+        )?;
+        ledger.flush()?;
+
+        let entries = read_entries(&config.path)?;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].run_id, "run_1");
+        assert_eq!(entries[0].routing_key, "transactions.visa.test_slug");
+        assert_eq!(entries[0].transaction.transaction_id, "tx_1");
+        assert!(entries[0].expectation.should_match);
+        assert_eq!(
+            entries[1].expectation,
+            Expectation {
+                token_known: false,
+                mid_known: true,
+                injection: Some(Injection::UnknownToken),
+                should_match: false,
+            }
+        );
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn full_ledgers_are_rotated() -> Result<()> {
+        let dir = temp_dir("rotation")?;
+        let config = LedgerConfig {
+            path: dir.join("ledger.jsonl"),
+            max_bytes: 1,
+            run_id: None,
+        };
+
+        let mut ledger = Ledger::open(&config, "run_1".to_owned())?;
+        for _ in 0..3 {
+            ledger.record(
+                &transaction(),
+                "transactions.visa.test_slug",
+                Expectation::new(true, true, None),
+            )?;
+        }
+        ledger.flush()?;
+
+        let files = ledger_files(&config.path);
+        assert_eq!(
+            files,
+            vec![
+                dir.join("ledger.jsonl.1"),
+                dir.join("ledger.jsonl.2"),
+                dir.join("ledger.jsonl"),
+            ]
+        );
+        for file in &files {
+            assert_eq!(read_entries(file)?.len(), 1);
+        }
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
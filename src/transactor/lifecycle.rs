@@ -0,0 +1,325 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Duration, Utc};
+use color_eyre::{eyre::eyre, Result};
+use rand::Rng;
+use tracing::warn;
+
+use super::injection::Injection;
+use crate::models::{RetailerConfig, SettlementConfig, Transaction, TransactionType};
+
+/// The most settlements held at once, so that a long lag at a high rate cannot take up memory
+/// without bound.
+const MAX_PENDING: usize = 100_000;
+
+/// A settlement waiting for its due date, with the fault injected into its auth, if any.
+pub struct Settlement {
+    pub transaction: Transaction,
+    pub injection: Option<Injection>,
+}
+
+/// Splits purchases into an auth now and a settlement later, and holds the settlements until they
+/// are due.
+pub struct Lifecycles {
+    retailers: HashMap<String, SettlementConfig>,
+    /// Settlements keyed by due date, then by the order they were scheduled in.
+    pending: BTreeMap<(DateTime<Utc>, u64), Settlement>,
+    max_pending: usize,
+    scheduled: u64,
+    /// Whether the warning that `pending` is full has been logged since it last had room.
+    warned_full: bool,
+}
+
+impl Lifecycles {
+    /// # Errors
+    ///
+    /// Returns an error if a retailer's rates are not fractions, or its tip and partial rates add
+    /// up to more than 1.
+    pub fn from_config(retailers: &[RetailerConfig]) -> Result<Self> {
+        let retailers = retailers
+            .iter()
+            .filter_map(|retailer| {
+                retailer
+                    .settlement
+                    .as_ref()
+                    .map(|settlement| (&retailer.provider_slug, settlement))
+            })
+            .map(|(slug, config)| {
+                for (name, rate) in [
+                    ("tip_rate", config.tip_rate),
+                    ("partial_rate", config.partial_rate),
+                    ("never_settle_rate", config.never_settle_rate),
+                ] {
+                    if !(0.0..=1.0).contains(&rate) {
+                        return Err(eyre!(
+                            "settlement {name} {rate} for retailer {slug} is not between 0 and 1"
+                        ));
+                    }
+                }
+                if config.tip_rate + config.partial_rate > 1.0 {
+                    return Err(eyre!(
+                        "settlement tip_rate and partial_rate for retailer {slug} add up to more than 1"
+                    ));
+                }
+                Ok((slug.clone(), config.clone()))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            retailers,
+            pending: BTreeMap::new(),
+            max_pending: MAX_PENDING,
+            scheduled: 0,
+            warned_full: false,
+        })
+    }
+
+    /// Whether purchases from this retailer and payment provider are split into auths and
+    /// settlements. While too many settlements are waiting, purchases are sent whole instead.
+    pub fn applies(&mut self, retailer: &str, payment_provider: &str) -> bool {
+        let configured = self.retailers.get(retailer).is_some_and(|config| {
+            config.payment_providers.is_empty()
+                || config
+                    .payment_providers
+                    .iter()
+                    .any(|provider| provider == payment_provider)
+        });
+        if !configured {
+            return false;
+        }
+
+        if self.pending.len() < self.max_pending {
+            self.warned_full = false;
+            return true;
+        }
+        if !self.warned_full {
+            warn!(
+                pending = self.pending.len(),
+                "too many settlements waiting, so purchases are sent whole until some are due"
+            );
+            self.warned_full = true;
+        }
+        false
+    }
+
+    /// Turns a purchase into an auth, and schedules its settlement after the configured lag
+    /// unless it is never to settle. The settlement is dated when it falls due.
+    pub fn authorise(
+        &mut self,
+        transaction: &mut Transaction,
+        injection: Option<Injection>,
+        now: DateTime<Utc>,
+        rng: &mut impl Rng,
+    ) {
+        let Some(config) = self.retailers.get(&transaction.merchant_name) else {
+            return;
+        };
+        transaction.transaction_type = TransactionType::Auth;
+
+        if config.never_settle_rate > 0.0 && rng.gen_bool(config.never_settle_rate) {
+            return;
+        }
+
+        let lag = Duration::seconds(
+            i64::from(config.lag_secs) + i64::from(rng.gen_range(0..=config.lag_jitter_secs)),
+        );
+        let due = now + lag;
+        let settlement = Transaction {
+            amount: drift(transaction.amount, config, rng),
+            transaction_type: TransactionType::Settlement,
+            transaction_date: due,
+            ..transaction.clone()
+        };
+
+        self.pending.insert(
+            (due, self.scheduled),
+            Settlement {
+                transaction: settlement,
+                injection,
+            },
+        );
+        self.scheduled += 1;
+    }
+
+    /// Takes the earliest settlement that is due by `now`.
+    pub fn next_due(&mut self, now: DateTime<Utc>) -> Option<Settlement> {
+        let entry = self.pending.first_entry()?;
+        if entry.key().0 <= now {
+            Some(entry.remove())
+        } else {
+            None
+        }
+    }
+
+    /// The number of settlements still waiting to be sent.
+    #[must_use]
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Warns about auths left unsettled when the run stopped.
+    pub fn finish(&self) {
+        if !self.pending.is_empty() {
+            warn!(
+                pending = self.pending.len(),
+                "stopped before every settlement was due, so some auths were not settled"
+            );
+        }
+    }
+}
+
+/// Returns the amount a settlement is for: the auth amount plus a tip, part of the auth amount,
+/// or the auth amount itself.
+fn drift(amount: i64, config: &SettlementConfig, rng: &mut impl Rng) -> i64 {
+    if config.tip_rate + config.partial_rate == 0.0 || amount <= 1 {
+        return amount;
+    }
+
+    let draw = rng.gen::<f64>();
+    if draw < config.tip_rate {
+        let max_tip = (amount * i64::from(config.max_tip_percent) / 100).max(1);
+        amount + rng.gen_range(1..=max_tip)
+    } else if draw < config.tip_rate + config.partial_rate {
+        rng.gen_range(1..amount)
+    } else {
+        amount
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn settlement_config() -> SettlementConfig {
+        SettlementConfig {
+            payment_providers: vec!["visa".to_owned()],
+            lag_secs: 60,
+            lag_jitter_secs: 0,
+            tip_rate: 0.0,
+            max_tip_percent: 20,
+            partial_rate: 0.0,
+            never_settle_rate: 0.0,
+        }
+    }
+
+    fn retailer(settlement: SettlementConfig) -> RetailerConfig {
+        RetailerConfig {
+            provider_slug: "test_slug".to_owned(),
+            amount_min: Some(10),
+            amount_max: Some(100),
+            amounts: None,
+            provider_amounts: HashMap::new(),
+            percentage: vec![("visa".to_owned(), 100)],
+            currencies: vec![],
+            identifier_type_percentage: None,
+            weight: None,
+            transactions_per_second: None,
+            refund_rate: 0.0,
+            partial_refund_rate: 0.0,
+            void_rate: 0.0,
+            settlement: Some(settlement),
+            timestamps: None,
+        }
+    }
+
+    fn purchase() -> Transaction {
+        Transaction {
+            amount: 1000,
+            merchant_name: "test_slug".to_owned(),
+            transaction_id: "tx_1".to_owned(),
+            identifier: "mid_1".to_owned(),
+            token: "token_1".to_owned(),
+            ..Transaction::fixture()
+        }
+    }
+
+    #[test]
+    fn purchases_are_sent_whole_while_too_many_settlements_wait() -> Result<()> {
+        let mut lifecycles = Lifecycles::from_config(&[retailer(settlement_config())])?;
+        lifecycles.max_pending = 1;
+        let mut rng = StdRng::seed_from_u64(1);
+        let now = Utc::now();
+
+        assert!(lifecycles.applies("test_slug", "visa"));
+        lifecycles.authorise(&mut purchase(), None, now, &mut rng);
+        assert!(!lifecycles.applies("test_slug", "visa"));
+
+        assert!(lifecycles.next_due(now + Duration::seconds(60)).is_some());
+        assert!(lifecycles.applies("test_slug", "visa"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn settlements_follow_their_auth_after_the_lag() -> Result<()> {
+        let mut lifecycles = Lifecycles::from_config(&[retailer(settlement_config())])?;
+        let mut rng = StdRng::seed_from_u64(1);
+        let now = Utc::now();
+
+        assert!(lifecycles.applies("test_slug", "visa"));
+        assert!(!lifecycles.applies("test_slug", "amex"));
+
+        let mut auth = Transaction {
+            transaction_date: now,
+            ..purchase()
+        };
+        lifecycles.authorise(&mut auth, None, now, &mut rng);
+        assert_eq!(auth.transaction_type, TransactionType::Auth);
+
+        assert!(lifecycles.next_due(now + Duration::seconds(59)).is_none());
+        let settlement = lifecycles
+            .next_due(now + Duration::seconds(60))
+            .expect("a due settlement")
+            .transaction;
+
+        assert_eq!(settlement.transaction_type, TransactionType::Settlement);
+        assert_eq!(settlement.transaction_id, auth.transaction_id);
+        assert_eq!(settlement.auth_code, auth.auth_code);
+        assert_eq!(settlement.identifier, auth.identifier);
+        assert_eq!(settlement.amount, 1000);
+        assert_eq!(settlement.transaction_date, now + Duration::seconds(60));
+        assert_eq!(auth.transaction_date, now);
+        assert_eq!(lifecycles.pending(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn tips_increase_the_settled_amount() -> Result<()> {
+        let mut lifecycles = Lifecycles::from_config(&[retailer(SettlementConfig {
+            tip_rate: 1.0,
+            lag_secs: 0,
+            ..settlement_config()
+        })])?;
+        let mut rng = StdRng::seed_from_u64(1);
+        let now = Utc::now();
+
+        for _ in 0..100 {
+            lifecycles.authorise(&mut purchase(), None, now, &mut rng);
+            let settlement = lifecycles.next_due(now).expect("a due settlement");
+            assert!((1001..=1200).contains(&settlement.transaction.amount));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn never_settled_auths_have_no_settlement() -> Result<()> {
+        let mut lifecycles = Lifecycles::from_config(&[retailer(SettlementConfig {
+            never_settle_rate: 1.0,
+            ..settlement_config()
+        })])?;
+        let mut auth = purchase();
+
+        lifecycles.authorise(&mut auth, None, Utc::now(), &mut StdRng::seed_from_u64(1));
+
+        assert_eq!(auth.transaction_type, TransactionType::Auth);
+        assert_eq!(lifecycles.pending(), 0);
+
+        Ok(())
+    }
+}
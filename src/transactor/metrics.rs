@@ -0,0 +1,122 @@
+use std::time::Duration;
+
+use axum::{routing::get, Router};
+use color_eyre::{eyre::eyre, Result};
+use metrics::{counter, gauge, histogram};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+
+use super::server;
+use crate::models::{MetricsConfig, Transaction};
+
+/// Transactions published, by retailer, payment provider and routing key.
+pub const PUBLISHED: &str = "atalanta_transactions_published_total";
+/// How long each publish took, including any wait for the broker to confirm.
+pub const PUBLISH_LATENCY: &str = "atalanta_publish_latency_seconds";
+/// The rate the load profile is asking for, in transactions per second.
+pub const TARGET_RATE: &str = "atalanta_target_rate";
+/// The rate transactions were actually published at over the last report interval.
+pub const ACTUAL_RATE: &str = "atalanta_actual_rate";
+/// Publishes that failed or that the broker did not take, by kind.
+pub const ERRORS: &str = "atalanta_errors_total";
+
+const LATENCY_BUCKETS: [f64; 12] = [
+    0.000_1, 0.000_25, 0.000_5, 0.001, 0.002_5, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 1.0,
+];
+
+/// Counts a published transaction and how long it took to publish. Without a recorder installed
+/// by [`serve`], this and the other recording functions do nothing.
+pub fn record_published(transaction: &Transaction, routing_key: &str, latency: Duration) {
+    counter!(
+        PUBLISHED,
+        "retailer" => transaction.merchant_name.clone(),
+        "payment_provider" => transaction.payment_provider.clone(),
+        "routing_key" => routing_key.to_owned(),
+    )
+    .increment(1);
+    histogram!(PUBLISH_LATENCY).record(latency);
+}
+
+/// Counts an error, such as `publish`, `unroutable` or `rejected`.
+pub fn record_error(kind: &'static str) {
+    counter!(ERRORS, "kind" => kind).increment(1);
+}
+
+pub fn set_target_rate(rate: f64) {
+    gauge!(TARGET_RATE).set(rate);
+}
+
+pub fn set_actual_rate(rate: f64) {
+    gauge!(ACTUAL_RATE).set(rate);
+}
+
+/// Installs the Prometheus recorder and serves `/metrics` from a background thread for as long
+/// as the transactor runs.
+///
+/// # Errors
+///
+/// Returns an error if a recorder is already installed or the address cannot be bound.
+pub fn serve(config: &MetricsConfig) -> Result<PrometheusHandle> {
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Full(PUBLISH_LATENCY.to_owned()), &LATENCY_BUCKETS)?
+        .install_recorder()
+        .map_err(|e| eyre!("failed to install the metrics recorder: {e}"))?;
+
+    let served = handle.clone();
+    let app = Router::new().route(
+        "/metrics",
+        get(move || async move {
+            // Fold histogram samples into the buckets before they are served.
+            served.run_upkeep();
+            served.render()
+        }),
+    );
+    server::spawn("metrics", config.address, app)?;
+
+    Ok(handle)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn published_transactions_are_rendered_by_label() -> Result<()> {
+        let recorder = PrometheusBuilder::new()
+            .set_buckets_for_metric(Matcher::Full(PUBLISH_LATENCY.to_owned()), &LATENCY_BUCKETS)?
+            .build_recorder();
+        let handle = recorder.handle();
+        let transaction = Transaction {
+            merchant_name: "test_slug".to_owned(),
+            transaction_id: "tx_1".to_owned(),
+            identifier: "mid_1".to_owned(),
+            token: "token_1".to_owned(),
+            ..Transaction::fixture()
+        };
+
+        metrics::with_local_recorder(&recorder, || {
+            record_published(
+                &transaction,
+                "transactions.visa.test_slug",
+                Duration::from_millis(2),
+            );
+            record_published(
+                &transaction,
+                "transactions.visa.test_slug",
+                Duration::from_millis(2),
+            );
+            record_error("unroutable");
+            set_target_rate(100.0);
+        });
+        let rendered = handle.render();
+
+        assert!(rendered.contains(
+            r#"atalanta_transactions_published_total{retailer="test_slug",payment_provider="visa",routing_key="transactions.visa.test_slug"} 2"#
+        ));
+        assert!(rendered.contains(r#"atalanta_publish_latency_seconds_bucket{le="0.0025"} 2"#));
+        assert!(rendered.contains(r#"atalanta_errors_total{kind="unroutable"} 1"#));
+        assert!(rendered.contains("atalanta_target_rate 100"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,16 @@
+pub mod amount;
+pub mod clock;
+pub mod control;
+pub mod injection;
+pub mod ledger;
+pub mod lifecycle;
+pub mod metrics;
+pub mod pacer;
+pub mod pool;
+pub mod profile;
+pub mod replay;
+pub mod reversal;
+pub mod server;
+pub mod sink;
+pub mod summary;
+pub mod timestamps;
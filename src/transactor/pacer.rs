@@ -0,0 +1,183 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use tracing::info;
+
+use super::metrics;
+
+/// A token bucket that releases transactions at a target rate.
+///
+/// Tokens accrue continuously, so time spent generating and publishing is accounted for, and
+/// high rates are served in bursts of up to `burst` worth of tokens rather than one at a time.
+pub struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+    burst: Duration,
+}
+
+impl TokenBucket {
+    #[must_use]
+    pub fn new(burst: Duration) -> Self {
+        Self {
+            tokens: 0.0,
+            last_refill: Instant::now(),
+            burst,
+        }
+    }
+
+    /// Waits up to `max_wait` for tokens to become available at `rate` per second, and returns the
+    /// number of transactions that may be sent now. Returns zero if no token arrived in time.
+    pub fn acquire(&mut self, rate: f64, max_wait: Duration) -> u64 {
+        self.refill(Instant::now(), rate);
+
+        if self.tokens < 1.0 {
+            let wait = if rate > 0.0 {
+                Duration::from_secs_f64((1.0 - self.tokens) / rate).min(max_wait)
+            } else {
+                max_wait
+            };
+            sleep(wait);
+            self.refill(Instant::now(), rate);
+        }
+
+        self.take()
+    }
+
+    fn refill(&mut self, now: Instant, rate: f64) {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        let capacity = (rate * self.burst.as_secs_f64()).max(1.0);
+        self.tokens = rate.mul_add(elapsed, self.tokens).min(capacity);
+        self.last_refill = now;
+    }
+
+    #[allow(clippy::cast_sign_loss)] // tokens are clamped to zero above
+    fn take(&mut self) -> u64 {
+        let available = self.tokens.floor().max(0.0);
+        self.tokens -= available;
+        available as u64
+    }
+}
+
+/// Periodically logs the configured rate against the rate at which transactions were published.
+pub struct RateReporter {
+    interval: Duration,
+    started: Instant,
+    window_start: Instant,
+    window_published: u64,
+    window_target: f64,
+    total_target: f64,
+    last_sample: Instant,
+}
+
+impl RateReporter {
+    #[must_use]
+    pub fn new(interval: Duration) -> Self {
+        let now = Instant::now();
+        Self {
+            interval,
+            started: now,
+            window_start: now,
+            window_published: 0,
+            window_target: 0.0,
+            total_target: 0.0,
+            last_sample: now,
+        }
+    }
+
+    /// Accumulates the current target rate and logs a report if the interval has elapsed.
+    pub fn observe(&mut self, rate: f64, published: &AtomicU64) {
+        let now = Instant::now();
+        let expected = rate
+            * now
+                .saturating_duration_since(self.last_sample)
+                .as_secs_f64();
+        self.window_target += expected;
+        self.total_target += expected;
+        self.last_sample = now;
+        metrics::set_target_rate(rate);
+
+        let elapsed = now.saturating_duration_since(self.window_start);
+        if elapsed < self.interval {
+            return;
+        }
+
+        let total = published.load(Ordering::Relaxed);
+        let secs = elapsed.as_secs_f64();
+        let achieved_rate = (total - self.window_published) as f64 / secs;
+        metrics::set_actual_rate(achieved_rate);
+        info!(
+            target_rate = format!("{:.2}", self.window_target / secs),
+            achieved_rate = format!("{achieved_rate:.2}"),
+            "publish rate"
+        );
+
+        self.window_start = now;
+        self.window_published = total;
+        self.window_target = 0.0;
+    }
+
+    /// Logs the configured and achieved rate over the whole run.
+    pub fn finish(&self, published: &AtomicU64) {
+        let secs = self.started.elapsed().as_secs_f64();
+        if secs <= 0.0 {
+            return;
+        }
+
+        let total = published.load(Ordering::Relaxed);
+        info!(
+            published = total,
+            elapsed_secs = format!("{secs:.1}"),
+            target_rate = format!("{:.2}", self.total_target / secs),
+            achieved_rate = format!("{:.2}", total as f64 / secs),
+            "overall publish rate"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn tokens_accrue_at_rate() {
+        let mut bucket = TokenBucket::new(Duration::from_secs(1));
+        let start = bucket.last_refill;
+
+        bucket.refill(start + Duration::from_millis(500), 1000.0);
+        assert_eq!(bucket.take(), 500);
+
+        bucket.refill(start + Duration::from_millis(501), 1000.0);
+        assert_eq!(bucket.take(), 1);
+    }
+
+    #[test]
+    fn tokens_are_capped_at_burst() {
+        let mut bucket = TokenBucket::new(Duration::from_millis(10));
+        let start = bucket.last_refill;
+
+        bucket.refill(start + Duration::from_secs(5), 10_000.0);
+        assert_eq!(bucket.take(), 100);
+    }
+
+    #[test]
+    fn slow_rates_keep_fractional_tokens() {
+        let mut bucket = TokenBucket::new(Duration::from_millis(10));
+        let start = bucket.last_refill;
+
+        bucket.refill(start + Duration::from_millis(500), 1.0);
+        assert_eq!(bucket.take(), 0);
+
+        bucket.refill(start + Duration::from_secs(1), 1.0);
+        assert_eq!(bucket.take(), 1);
+    }
+
+    #[test]
+    fn zero_rate_yields_nothing() {
+        let mut bucket = TokenBucket::new(Duration::from_millis(10));
+        assert_eq!(bucket.acquire(0.0, Duration::from_millis(1)), 0);
+    }
+}
@@ -0,0 +1,527 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::hash::BuildHasher;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use color_eyre::{eyre::eyre, Result};
+use flate2::read::MultiGzDecoder;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::models::StoreIdentifiers;
+
+/// How many malformed rows in a file are logged one by one before only being counted.
+const MALFORMED_ROWS_LOGGED: usize = 20;
+
+/// A payment card token from the Hermes database, with the card's first six and last four
+/// digits.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TokenRecord {
+    pub token: String,
+    #[serde(alias = "retailer")]
+    pub retailer_slug: String,
+    pub first_six: String,
+    pub last_four: String,
+    #[serde(alias = "payment")]
+    pub payment_slug: String,
+}
+
+/// The column names a headered tokens file may use.
+const TOKEN_COLUMNS: [&str; 7] = [
+    "token",
+    "retailer_slug",
+    "retailer",
+    "first_six",
+    "last_four",
+    "payment_slug",
+    "payment",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
+pub enum IdentifierType {
+    #[serde(rename = "PRIMARY")]
+    PrimaryMID,
+    #[serde(rename = "SECONDARY")]
+    SecondaryMID,
+    #[serde(rename = "PSIMI")]
+    Psimi,
+}
+
+impl fmt::Display for IdentifierType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PrimaryMID => write!(f, "PRIMARY"),
+            Self::SecondaryMID => write!(f, "SECONDARY"),
+            Self::Psimi => write!(f, "PSIMI"),
+        }
+    }
+}
+
+/// A merchant identifier from Harmonia's database.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct IdentifierRecord {
+    #[serde(alias = "retailer")]
+    pub retailer_slug: String,
+    #[serde(alias = "payment")]
+    pub payment_slug: String,
+    pub identifier: String,
+    pub identifier_type: IdentifierType,
+    #[serde(default)]
+    pub location_id: Option<String>,
+    #[serde(default)]
+    pub merchant_internal_id: Option<String>,
+}
+
+/// The column names a headered MIDs file may use.
+const IDENTIFIER_COLUMNS: [&str; 8] = [
+    "retailer_slug",
+    "retailer",
+    "payment_slug",
+    "payment",
+    "identifier",
+    "identifier_type",
+    "location_id",
+    "merchant_internal_id",
+];
+
+/// Reads the rows of a CSV file, which may be gzipped (`.gz`) and may start with a header row
+/// naming its columns. Without a header, columns are read in the order of `T`'s fields.
+///
+/// Malformed rows are logged with their line number and skipped.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be opened or read.
+pub fn read_csv<T: DeserializeOwned>(path: &Path, columns: &[&str]) -> Result<Vec<T>> {
+    let file = File::open(path).map_err(|e| eyre!("failed to open {}: {e}", path.display()))?;
+    let reader: Box<dyn Read> = if path.extension().is_some_and(|extension| extension == "gz") {
+        Box::new(MultiGzDecoder::new(BufReader::new(file)))
+    } else {
+        Box::new(file)
+    };
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+
+    let mut headers = None;
+    let mut rows = Vec::new();
+    let mut malformed = 0;
+    for (index, result) in rdr.records().enumerate() {
+        let position = match &result {
+            Ok(record) => record.position(),
+            Err(e) => e.position(),
+        };
+        let line = position.map_or(index as u64 + 1, csv::Position::line);
+        let parsed = result.map_err(eyre::Report::from).and_then(|record| {
+            if index == 0 && record.iter().any(|field| columns.contains(&field.trim())) {
+                headers = Some(record);
+                return Ok(None);
+            }
+            Ok(Some(record.deserialize::<T>(headers.as_ref())?))
+        });
+
+        match parsed {
+            Ok(Some(row)) => rows.push(row),
+            Ok(None) => {}
+            Err(e) => {
+                malformed += 1;
+                if malformed <= MALFORMED_ROWS_LOGGED {
+                    warn!("skipping malformed row at {}:{line}: {e}", path.display());
+                }
+            }
+        }
+    }
+
+    if malformed > 0 {
+        warn!("skipped {malformed} malformed rows in {}", path.display());
+    }
+
+    Ok(rows)
+}
+
+/// A retailer's tokens, indexed by payment provider.
+#[derive(Default)]
+pub struct TokenPool {
+    by_provider: HashMap<String, Vec<TokenRecord>>,
+    known: HashSet<String>,
+}
+
+impl TokenPool {
+    pub fn insert(&mut self, record: TokenRecord) {
+        self.known.insert(record.token.clone());
+        self.by_provider
+            .entry(record.payment_slug.clone())
+            .or_default()
+            .push(record);
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.by_provider.values().map(Vec::len).sum()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.by_provider.is_empty()
+    }
+
+    /// Whether the token is in the pool, for any payment provider.
+    #[must_use]
+    pub fn contains(&self, token: &str) -> bool {
+        self.known.contains(token)
+    }
+
+    #[must_use]
+    pub fn has_provider(&self, payment_provider: &str) -> bool {
+        self.by_provider.contains_key(payment_provider)
+    }
+
+    pub fn choose(&self, payment_provider: &str, rng: &mut impl Rng) -> Option<&TokenRecord> {
+        self.by_provider.get(payment_provider)?.choose(rng)
+    }
+}
+
+impl FromIterator<TokenRecord> for TokenPool {
+    fn from_iter<I: IntoIterator<Item = TokenRecord>>(records: I) -> Self {
+        let mut pool = Self::default();
+        for record in records {
+            pool.insert(record);
+        }
+        pool
+    }
+}
+
+/// A retailer's identifiers, indexed by payment provider and identifier type, along with the
+/// identifiers of each store grouped by location.
+#[derive(Default)]
+pub struct IdentifierPool {
+    by_provider: HashMap<String, BTreeMap<IdentifierType, Vec<IdentifierRecord>>>,
+    stores: HashMap<String, HashMap<String, StoreIdentifiers>>,
+    known: HashSet<String>,
+}
+
+impl IdentifierPool {
+    pub fn insert(&mut self, record: IdentifierRecord) {
+        self.known.insert(record.identifier.clone());
+        if let Some(location_id) = &record.location_id {
+            let store = self
+                .stores
+                .entry(record.payment_slug.clone())
+                .or_default()
+                .entry(location_id.clone())
+                .or_default();
+            // A store keeps the first identifier of each type it is listed with.
+            let slot = match record.identifier_type {
+                IdentifierType::PrimaryMID => &mut store.primary,
+                IdentifierType::SecondaryMID => &mut store.secondary,
+                IdentifierType::Psimi => &mut store.psimi,
+            };
+            slot.get_or_insert_with(|| record.identifier.clone());
+        }
+        self.by_provider
+            .entry(record.payment_slug.clone())
+            .or_default()
+            .entry(record.identifier_type)
+            .or_default()
+            .push(record);
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.by_provider
+            .values()
+            .flat_map(BTreeMap::values)
+            .map(Vec::len)
+            .sum()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.by_provider.is_empty()
+    }
+
+    /// Whether the identifier is in the pool, for any payment provider.
+    #[must_use]
+    pub fn contains(&self, identifier: &str) -> bool {
+        self.known.contains(identifier)
+    }
+
+    #[must_use]
+    pub fn has_provider(&self, payment_provider: &str) -> bool {
+        self.by_provider.contains_key(payment_provider)
+    }
+
+    /// The identifier types the payment provider has identifiers of.
+    pub fn types(&self, payment_provider: &str) -> impl Iterator<Item = IdentifierType> + '_ {
+        self.by_provider
+            .get(payment_provider)
+            .into_iter()
+            .flat_map(BTreeMap::keys)
+            .copied()
+    }
+
+    /// Returns every identifier the payment provider has for the identifier's store, if the
+    /// identifier has a location.
+    #[must_use]
+    pub fn store(&self, identifier: &IdentifierRecord) -> Option<&StoreIdentifiers> {
+        self.stores
+            .get(&identifier.payment_slug)?
+            .get(identifier.location_id.as_deref()?)
+    }
+
+    /// Picks one of the payment provider's identifiers of the given type, or of any type.
+    pub fn choose(
+        &self,
+        payment_provider: &str,
+        identifier_type: Option<IdentifierType>,
+        rng: &mut impl Rng,
+    ) -> Option<&IdentifierRecord> {
+        let by_type = self.by_provider.get(payment_provider)?;
+        if let Some(identifier_type) = identifier_type {
+            return by_type.get(&identifier_type)?.choose(rng);
+        }
+
+        let total = by_type.values().map(Vec::len).sum::<usize>();
+        if total == 0 {
+            return None;
+        }
+        let mut index = rng.gen_range(0..total);
+        for identifiers in by_type.values() {
+            if index < identifiers.len() {
+                return identifiers.get(index);
+            }
+            index -= identifiers.len();
+        }
+        None
+    }
+}
+
+impl FromIterator<IdentifierRecord> for IdentifierPool {
+    fn from_iter<I: IntoIterator<Item = IdentifierRecord>>(records: I) -> Self {
+        let mut pool = Self::default();
+        for record in records {
+            pool.insert(record);
+        }
+        pool
+    }
+}
+
+/// Loads the tokens for the given retailers into a pool per retailer.
+///
+/// # Errors
+///
+/// Returns an error if the tokens file cannot be read.
+pub fn load_token_pools<S: BuildHasher>(
+    path: &Path,
+    retailer_slugs: &HashSet<&str, S>,
+) -> Result<HashMap<String, TokenPool>> {
+    let mut pools: HashMap<String, TokenPool> = HashMap::new();
+    for record in read_csv::<TokenRecord>(path, &TOKEN_COLUMNS)? {
+        if retailer_slugs.contains(record.retailer_slug.as_str()) {
+            pools
+                .entry(record.retailer_slug.clone())
+                .or_default()
+                .insert(record);
+        }
+    }
+
+    for retailer_slug in retailer_slugs {
+        info!(
+            "loaded {} tokens from {} for retailer {retailer_slug}",
+            pools.get(*retailer_slug).map_or(0, TokenPool::len),
+            path.display()
+        );
+    }
+
+    Ok(pools)
+}
+
+/// The identifiers loaded for each retailer, along with every identifier in the MIDs file.
+#[derive(Default)]
+pub struct IdentifierPools {
+    pub by_retailer: HashMap<String, IdentifierPool>,
+    /// Every identifier in the file, including those of retailers that are not being run, which
+    /// a deliberately unknown identifier must not be.
+    pub known: HashSet<String>,
+}
+
+/// Loads the identifiers for the given retailers into a pool per retailer.
+///
+/// # Errors
+///
+/// Returns an error if the MIDs file cannot be read.
+pub fn load_identifier_pools<S: BuildHasher>(
+    path: &Path,
+    retailer_slugs: &HashSet<&str, S>,
+) -> Result<IdentifierPools> {
+    let mut pools = IdentifierPools::default();
+    for record in read_csv::<IdentifierRecord>(path, &IDENTIFIER_COLUMNS)? {
+        pools.known.insert(record.identifier.clone());
+        if retailer_slugs.contains(record.retailer_slug.as_str()) {
+            pools
+                .by_retailer
+                .entry(record.retailer_slug.clone())
+                .or_default()
+                .insert(record);
+        }
+    }
+
+    for retailer_slug in retailer_slugs {
+        info!(
+            "loaded {} identifiers from {} for retailer {retailer_slug}",
+            pools
+                .by_retailer
+                .get(*retailer_slug)
+                .map_or(0, IdentifierPool::len),
+            path.display()
+        );
+    }
+
+    Ok(pools)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use pretty_assertions::assert_eq;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn temp_dir(name: &str) -> Result<PathBuf> {
+        let dir = std::env::temp_dir().join(format!("atalanta-pool-{name}-{}", std::process::id()));
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+        fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+
+    #[test]
+    fn headerless_files_skip_malformed_rows() -> Result<()> {
+        let dir = temp_dir("headerless")?;
+        let path = dir.join("perf_mids.csv");
+        fs::write(
+            &path,
+            "costa,visa,costa0001,PRIMARY,,\n\
+             costa,visa,costa0002,NOT_A_TYPE,,\n\
+             costa,amex,costa0003,PRIMARY,store_1,brand_1\n",
+        )?;
+
+        let records = read_csv::<IdentifierRecord>(&path, &IDENTIFIER_COLUMNS)?;
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].location_id, None);
+        assert_eq!(records[1].location_id.as_deref(), Some("store_1"));
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn headered_gzipped_files_are_read_by_column_name() -> Result<()> {
+        let dir = temp_dir("gzip")?;
+        let path = dir.join("hermes_tokens.csv.gz");
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(
+            b"payment,token,retailer,first_six,last_four\n\
+              visa,token_1,costa,424242,4242\n",
+        )?;
+        fs::write(&path, encoder.finish()?)?;
+
+        let records = read_csv::<TokenRecord>(&path, &TOKEN_COLUMNS)?;
+
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].token, "token_1");
+        assert_eq!(records[0].payment_slug, "visa");
+        assert_eq!(records[0].retailer_slug, "costa");
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    fn identifier(identifier: &str, identifier_type: IdentifierType) -> IdentifierRecord {
+        IdentifierRecord {
+            retailer_slug: "costa".to_owned(),
+            payment_slug: "visa".to_owned(),
+            identifier: identifier.to_owned(),
+            identifier_type,
+            location_id: None,
+            merchant_internal_id: None,
+        }
+    }
+
+    #[test]
+    fn identifiers_are_grouped_by_store() {
+        let at_store = |id: &str, identifier_type, location_id: &str| IdentifierRecord {
+            location_id: Some(location_id.to_owned()),
+            ..identifier(id, identifier_type)
+        };
+        let primary = at_store("mid_1", IdentifierType::PrimaryMID, "store_1");
+        let pool = [
+            primary.clone(),
+            at_store("store_id_1", IdentifierType::SecondaryMID, "store_1"),
+            at_store("psimi_1", IdentifierType::Psimi, "store_1"),
+            at_store("mid_2", IdentifierType::PrimaryMID, "store_2"),
+            identifier("mid_3", IdentifierType::PrimaryMID),
+        ]
+        .into_iter()
+        .collect::<IdentifierPool>();
+
+        assert_eq!(
+            pool.store(&primary),
+            Some(&StoreIdentifiers {
+                primary: Some("mid_1".to_owned()),
+                secondary: Some("store_id_1".to_owned()),
+                psimi: Some("psimi_1".to_owned()),
+            })
+        );
+        assert_eq!(
+            pool.store(&at_store("mid_2", IdentifierType::PrimaryMID, "store_2"))
+                .and_then(|store| store.psimi.as_deref()),
+            None
+        );
+        assert_eq!(
+            pool.store(&identifier("mid_3", IdentifierType::PrimaryMID)),
+            None
+        );
+    }
+
+    #[test]
+    fn identifier_pools_choose_by_provider_and_type() {
+        let mut pool = IdentifierPool::default();
+        pool.insert(identifier("mid_1", IdentifierType::PrimaryMID));
+        pool.insert(identifier("psimi_1", IdentifierType::Psimi));
+        let mut rng = StdRng::seed_from_u64(1);
+
+        assert_eq!(pool.len(), 2);
+        assert!(pool.contains("psimi_1"));
+        assert_eq!(
+            pool.types("visa").collect::<Vec<_>>(),
+            vec![IdentifierType::PrimaryMID, IdentifierType::Psimi]
+        );
+        assert_eq!(
+            pool.choose("visa", Some(IdentifierType::Psimi), &mut rng)
+                .map(|record| record.identifier.as_str()),
+            Some("psimi_1")
+        );
+        assert!(pool
+            .choose("visa", Some(IdentifierType::SecondaryMID), &mut rng)
+            .is_none());
+        assert!(pool.choose("amex", None, &mut rng).is_none());
+        assert!(pool.choose("visa", None, &mut rng).is_some());
+    }
+}
@@ -0,0 +1,591 @@
+use std::f64::consts::PI;
+use std::fs::File;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use color_eyre::{eyre::eyre, Result};
+use serde::Deserialize;
+use tracing::info;
+
+use crate::models::{LoadPhase, LoadShape, TransactorConfig};
+
+/// The rate a load profile asks for at a point in time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Target {
+    pub phase: usize,
+    pub rate: f64,
+}
+
+enum Shape {
+    Constant(f64),
+    Ramp {
+        from: f64,
+        to: f64,
+    },
+    Step {
+        from: f64,
+        step: f64,
+        step_secs: u64,
+    },
+    Spike {
+        base: f64,
+        peak: f64,
+        offset: Duration,
+        spike: Duration,
+    },
+    Sine {
+        mean: f64,
+        amplitude: f64,
+        period_secs: f64,
+    },
+    Trace {
+        rates: Vec<f64>,
+        seconds_per_hour: u64,
+    },
+}
+
+struct Phase {
+    name: String,
+    duration: Option<Duration>,
+    shape: Shape,
+}
+
+/// A sequence of load phases that give the target transaction rate over wall-clock time.
+pub struct LoadProfile {
+    phases: Vec<Phase>,
+}
+
+impl LoadProfile {
+    /// A profile that runs at a constant rate forever.
+    #[must_use]
+    pub fn constant(rate: f64) -> Self {
+        Self {
+            phases: vec![Phase {
+                name: "constant".to_owned(),
+                duration: None,
+                shape: Shape::Constant(rate),
+            }],
+        }
+    }
+
+    /// Builds the profile described by `config.phases`, falling back to a constant
+    /// `config.transactions_per_second` if no phases are configured. Without either, the rate is
+    /// the sum of the retailers' own `transactions_per_second`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a phase is invalid, a trace file cannot be read, or no rate is set.
+    pub fn from_config(config: &TransactorConfig) -> Result<Self> {
+        if config.phases.is_empty() {
+            let rate = config
+                .transactions_per_second
+                .or_else(|| {
+                    config
+                        .retailers
+                        .iter()
+                        .map(|retailer| retailer.transactions_per_second)
+                        .sum()
+                })
+                .ok_or_else(|| {
+                    eyre!("transactor config has no phases or transactions_per_second")
+                })?;
+            return Ok(Self::constant(rate as f64));
+        }
+
+        Self::from_phases(&config.phases)
+    }
+
+    /// Builds a profile that runs the given phases in order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there are no phases, a phase is invalid, or a trace file cannot be read.
+    pub fn from_phases(phases: &[LoadPhase]) -> Result<Self> {
+        if phases.is_empty() {
+            return Err(eyre!("a load profile needs at least one phase"));
+        }
+
+        let phases = phases
+            .iter()
+            .enumerate()
+            .map(|(idx, phase)| Phase::from_config(idx, phase))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { phases })
+    }
+
+    /// Returns the target rate at `elapsed` since the start of the profile,
+    /// or `None` once every phase has finished.
+    #[must_use]
+    pub fn target(&self, elapsed: Duration) -> Option<Target> {
+        let mut phase_start = Duration::ZERO;
+        for (idx, phase) in self.phases.iter().enumerate() {
+            match phase.duration {
+                // A phase that would end past the longest possible duration never ends.
+                Some(duration)
+                    if phase_start
+                        .checked_add(duration)
+                        .is_some_and(|end| elapsed >= end) =>
+                {
+                    phase_start += duration;
+                }
+                _ => {
+                    return Some(Target {
+                        phase: idx,
+                        rate: phase.rate_at(elapsed.saturating_sub(phase_start)),
+                    })
+                }
+            }
+        }
+        None
+    }
+
+    #[must_use]
+    pub fn phase_name(&self, phase: usize) -> &str {
+        self.phases.get(phase).map_or("", |p| p.name.as_str())
+    }
+
+    /// Whether the phase runs for a fixed time, rather than forever.
+    #[must_use]
+    pub fn phase_ends(&self, phase: usize) -> bool {
+        self.phases.get(phase).is_some_and(|p| p.duration.is_some())
+    }
+}
+
+/// Works out a phase's length from a number of steps or hours, each `secs` long.
+fn multiply_secs(name: &str, count: u64, secs: u64) -> Result<u64> {
+    count
+        .checked_mul(secs)
+        .ok_or_else(|| eyre!("phase {name} is too long"))
+}
+
+impl Phase {
+    fn from_config(idx: usize, config: &LoadPhase) -> Result<Self> {
+        let name = config
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("phase-{}", idx + 1));
+
+        let secs = |s: u64| -> Result<Duration> {
+            if s == 0 {
+                Err(eyre!("phase {name} must have a non-zero duration"))
+            } else {
+                Ok(Duration::from_secs(s))
+            }
+        };
+
+        let (duration, shape) = match &config.shape {
+            LoadShape::Soak {
+                rate,
+                duration_secs,
+            } => (secs(*duration_secs)?, Shape::Constant(*rate)),
+            LoadShape::Ramp {
+                from,
+                to,
+                duration_secs,
+            } => (
+                secs(*duration_secs)?,
+                Shape::Ramp {
+                    from: *from,
+                    to: *to,
+                },
+            ),
+            LoadShape::Step {
+                from,
+                step,
+                step_secs,
+                steps,
+            } => (
+                secs(multiply_secs(&name, *steps, *step_secs)?)?,
+                Shape::Step {
+                    from: *from,
+                    step: *step,
+                    step_secs: *step_secs,
+                },
+            ),
+            LoadShape::Spike {
+                base,
+                peak,
+                offset_secs,
+                spike_secs,
+                duration_secs,
+            } => (
+                secs(*duration_secs)?,
+                Shape::Spike {
+                    base: *base,
+                    peak: *peak,
+                    offset: Duration::from_secs(*offset_secs),
+                    spike: Duration::from_secs(*spike_secs),
+                },
+            ),
+            LoadShape::Sine {
+                mean,
+                amplitude,
+                period_secs,
+                duration_secs,
+            } => {
+                if *period_secs == 0 {
+                    return Err(eyre!("phase {name} must have a non-zero sine period"));
+                }
+                (
+                    secs(*duration_secs)?,
+                    Shape::Sine {
+                        mean: *mean,
+                        amplitude: *amplitude,
+                        period_secs: *period_secs as f64,
+                    },
+                )
+            }
+            LoadShape::Trace {
+                path,
+                seconds_per_hour,
+                scale,
+            } => {
+                let rates = load_trace(path, *scale)?;
+                let duration = multiply_secs(&name, rates.len() as u64, *seconds_per_hour)?;
+                (
+                    secs(duration)?,
+                    Shape::Trace {
+                        rates,
+                        seconds_per_hour: *seconds_per_hour,
+                    },
+                )
+            }
+        };
+
+        let phase = Self {
+            name,
+            duration: Some(duration),
+            shape,
+        };
+        phase.validate()?;
+        Ok(phase)
+    }
+
+    fn validate(&self) -> Result<()> {
+        let rates: Vec<f64> = match &self.shape {
+            Shape::Constant(rate) => vec![*rate],
+            Shape::Ramp { from, to } => vec![*from, *to],
+            Shape::Step {
+                from,
+                step,
+                step_secs,
+            } => {
+                // The rate changes monotonically, so the first and last steps bound it.
+                let steps = self.duration.map_or(0, |d| d.as_secs() / step_secs);
+                vec![*from, step.mul_add(steps.saturating_sub(1) as f64, *from)]
+            }
+            Shape::Spike { base, peak, .. } => vec![*base, *peak],
+            Shape::Sine {
+                mean, amplitude, ..
+            } => vec![*mean - amplitude.abs()],
+            Shape::Trace { rates, .. } => rates.clone(),
+        };
+
+        if rates.iter().all(|rate| rate.is_finite() && *rate >= 0.0) {
+            Ok(())
+        } else {
+            Err(eyre!(
+                "phase {} has a negative or non-finite rate",
+                self.name
+            ))
+        }
+    }
+
+    fn rate_at(&self, elapsed: Duration) -> f64 {
+        let rate = match &self.shape {
+            Shape::Constant(rate) => *rate,
+            Shape::Ramp { from, to } => {
+                let progress = self
+                    .duration
+                    .map_or(1.0, |d| (elapsed.as_secs_f64() / d.as_secs_f64()).min(1.0));
+                (to - from).mul_add(progress, *from)
+            }
+            Shape::Step {
+                from,
+                step,
+                step_secs,
+            } => (elapsed.as_secs() / step_secs) as f64 * step + from,
+            Shape::Spike {
+                base,
+                peak,
+                offset,
+                spike,
+            } => {
+                if elapsed >= *offset && elapsed < offset.saturating_add(*spike) {
+                    *peak
+                } else {
+                    *base
+                }
+            }
+            Shape::Sine {
+                mean,
+                amplitude,
+                period_secs,
+            } => amplitude.mul_add(
+                (2.0 * PI * elapsed.as_secs_f64() / period_secs).sin(),
+                *mean,
+            ),
+            Shape::Trace {
+                rates,
+                seconds_per_hour,
+            } => {
+                let hour = (elapsed.as_secs() / seconds_per_hour) as usize;
+                rates.get(hour).copied().unwrap_or(0.0)
+            }
+        };
+        rate.max(0.0)
+    }
+}
+
+/// Reads a headerless `hour,transactions` CSV file into a per-second rate for each hour.
+fn load_trace(path: &Path, scale: f64) -> Result<Vec<f64>> {
+    #[derive(Deserialize)]
+    struct TraceRecord {
+        _hour: String,
+        transactions: f64,
+    }
+
+    let file =
+        File::open(path).map_err(|e| eyre!("failed to open load trace {}: {e}", path.display()))?;
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(file);
+
+    let rates = rdr
+        .deserialize()
+        .map(|result| result.map(|record: TraceRecord| record.transactions / 3600.0 * scale))
+        .collect::<Result<Vec<_>, csv::Error>>()?;
+
+    if rates.is_empty() {
+        return Err(eyre!("load trace {} is empty", path.display()));
+    }
+
+    info!(
+        hours = rates.len(),
+        "loaded load trace from {}",
+        path.display()
+    );
+
+    Ok(rates)
+}
+
+/// Tracks the target and achieved rate of each phase as the profile runs.
+pub struct PhaseMonitor {
+    phase: Option<usize>,
+    started: Instant,
+    last_sample: Instant,
+    published: u64,
+    target_total: f64,
+}
+
+impl Default for PhaseMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PhaseMonitor {
+    #[must_use]
+    pub fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            phase: None,
+            started: now,
+            last_sample: now,
+            published: 0,
+            target_total: 0.0,
+        }
+    }
+
+    /// Records the current target, logging a summary whenever the phase changes.
+    pub fn observe(&mut self, profile: &LoadProfile, target: Target) {
+        let now = Instant::now();
+        if self.phase != Some(target.phase) {
+            self.finish(profile);
+            info!(
+                phase = profile.phase_name(target.phase),
+                target_rate = target.rate,
+                "starting load phase"
+            );
+            self.phase = Some(target.phase);
+            self.started = now;
+            self.last_sample = now;
+            self.published = 0;
+            self.target_total = 0.0;
+        }
+
+        self.target_total += target.rate * (now - self.last_sample).as_secs_f64();
+        self.last_sample = now;
+    }
+
+    pub const fn record_publish(&mut self, count: u64) {
+        self.published += count;
+    }
+
+    /// Logs the target and achieved rate for the phase in progress, if any.
+    pub fn finish(&mut self, profile: &LoadProfile) {
+        let Some(phase) = self.phase.take() else {
+            return;
+        };
+
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let (target_rate, achieved_rate) = if elapsed > 0.0 {
+            (self.target_total / elapsed, self.published as f64 / elapsed)
+        } else {
+            (0.0, 0.0)
+        };
+
+        info!(
+            phase = profile.phase_name(phase),
+            published = self.published,
+            elapsed_secs = format!("{elapsed:.1}"),
+            target_rate = format!("{target_rate:.2}"),
+            achieved_rate = format!("{achieved_rate:.2}"),
+            "finished load phase"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{InjectionConfig, SinkConfig};
+    use pretty_assertions::assert_eq;
+
+    fn phase(shape: LoadShape) -> LoadPhase {
+        LoadPhase { name: None, shape }
+    }
+
+    fn profile(phases: Vec<LoadPhase>) -> Result<LoadProfile> {
+        let config = TransactorConfig {
+            retailers: vec![],
+            transactions_per_second: Some(5),
+            phases,
+            publish_workers: 1,
+            max_in_flight: 1000,
+            total_transactions: None,
+            duration_secs: None,
+            seed: None,
+            start_date: None,
+            injection: InjectionConfig::default(),
+            ledger: None,
+            replay: None,
+            sink: SinkConfig::default(),
+            metrics: None,
+            control: None,
+        };
+        LoadProfile::from_config(&config)
+    }
+
+    fn rate_at(profile: &LoadProfile, secs: u64) -> Option<f64> {
+        profile
+            .target(Duration::from_secs(secs))
+            .map(|target| target.rate)
+    }
+
+    #[test]
+    fn no_phases_is_constant_forever() -> Result<()> {
+        let profile = profile(vec![])?;
+        assert_eq!(rate_at(&profile, 0), Some(5.0));
+        assert_eq!(rate_at(&profile, 1_000_000), Some(5.0));
+        Ok(())
+    }
+
+    #[test]
+    fn phases_run_in_order_then_finish() -> Result<()> {
+        let profile = profile(vec![
+            phase(LoadShape::Ramp {
+                from: 0.0,
+                to: 100.0,
+                duration_secs: 10,
+            }),
+            phase(LoadShape::Spike {
+                base: 10.0,
+                peak: 50.0,
+                offset_secs: 5,
+                spike_secs: 2,
+                duration_secs: 10,
+            }),
+            phase(LoadShape::Step {
+                from: 1.0,
+                step: 2.0,
+                step_secs: 5,
+                steps: 3,
+            }),
+        ])?;
+
+        assert_eq!(rate_at(&profile, 5), Some(50.0));
+        assert_eq!(rate_at(&profile, 14), Some(10.0));
+        assert_eq!(rate_at(&profile, 16), Some(50.0));
+        assert_eq!(rate_at(&profile, 18), Some(10.0));
+        assert_eq!(rate_at(&profile, 20), Some(1.0));
+        assert_eq!(rate_at(&profile, 31), Some(5.0));
+        assert_eq!(rate_at(&profile, 35), None);
+        assert_eq!(profile.phase_name(1), "phase-2");
+        Ok(())
+    }
+
+    #[test]
+    fn sine_rate_is_clamped_at_zero() -> Result<()> {
+        let profile = profile(vec![phase(LoadShape::Sine {
+            mean: 10.0,
+            amplitude: 10.0,
+            period_secs: 4,
+            duration_secs: 8,
+        })])?;
+
+        assert_eq!(rate_at(&profile, 1), Some(20.0));
+        assert_eq!(rate_at(&profile, 3), Some(0.0));
+        Ok(())
+    }
+
+    #[test]
+    fn negative_rate_is_rejected() {
+        let result = profile(vec![phase(LoadShape::Soak {
+            rate: -1.0,
+            duration_secs: 10,
+        })]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn invalid_steps_are_rejected() {
+        let step = |step: f64, step_secs: u64, steps: u64| {
+            profile(vec![phase(LoadShape::Step {
+                from: 10.0,
+                step,
+                step_secs,
+                steps,
+            })])
+        };
+
+        assert!(step(-2.0, 5, 5).is_ok());
+        assert!(step(-5.0, 5, 5).is_err());
+        assert!(step(f64::NAN, 5, 5).is_err());
+        assert!(step(1.0, 5, 0).is_err());
+        assert!(step(1.0, u64::MAX, 2).is_err());
+    }
+
+    #[test]
+    fn phases_too_long_to_add_up_do_not_overflow() -> Result<()> {
+        let profile = profile(vec![
+            phase(LoadShape::Spike {
+                base: 1.0,
+                peak: 2.0,
+                offset_secs: u64::MAX - 1,
+                spike_secs: u64::MAX,
+                duration_secs: u64::MAX,
+            }),
+            phase(LoadShape::Soak {
+                rate: 3.0,
+                duration_secs: u64::MAX,
+            }),
+        ])?;
+
+        assert_eq!(rate_at(&profile, 0), Some(1.0));
+        assert_eq!(rate_at(&profile, u64::MAX - 1), Some(2.0));
+        assert_eq!(rate_at(&profile, u64::MAX), Some(3.0));
+        Ok(())
+    }
+}
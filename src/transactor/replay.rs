@@ -0,0 +1,199 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use color_eyre::{eyre::eyre, Result};
+
+use crate::models::{ReplayConfig, Transaction};
+
+/// Reads captured transactions from a JSONL file, or a headered CSV file if the extension is
+/// anything other than `jsonl` or `json`. Ledger files are JSONL, so a ledger can be replayed.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or holds an invalid transaction.
+pub fn read_transactions(path: &Path) -> Result<Vec<Transaction>> {
+    let file = File::open(path)
+        .map_err(|e| eyre!("failed to open replay file {}: {e}", path.display()))?;
+    let jsonl = path
+        .extension()
+        .is_some_and(|extension| extension == "jsonl" || extension == "json");
+
+    if jsonl {
+        let mut transactions = Vec::new();
+        for (number, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            transactions.push(serde_json::from_str(&line).map_err(|e| {
+                eyre!(
+                    "invalid transaction at {}:{}: {e}",
+                    path.display(),
+                    number + 1
+                )
+            })?);
+        }
+        Ok(transactions)
+    } else {
+        csv::Reader::from_reader(file)
+            .deserialize()
+            .map(|record| {
+                record.map_err(|e| eyre!("invalid transaction in {}: {e}", path.display()))
+            })
+            .collect()
+    }
+}
+
+enum Timing {
+    Original { speed: f64 },
+    Fixed { rate: f64 },
+}
+
+/// Works out when each replayed transaction is due, relative to the start of the replay.
+pub struct ReplayPacer {
+    timing: Timing,
+    first: Option<DateTime<Utc>>,
+    sent: u64,
+}
+
+impl ReplayPacer {
+    /// # Errors
+    ///
+    /// Returns an error if both a speed and a rate are given, or either is not positive.
+    pub fn from_config(config: &ReplayConfig) -> Result<Self> {
+        let timing = match (config.speed, config.rate) {
+            (Some(_), Some(_)) => {
+                return Err(eyre!("replay takes either a speed or a rate, not both"));
+            }
+            (_, Some(rate)) if rate > 0.0 => Timing::Fixed { rate },
+            (None, None) => Timing::Original { speed: 1.0 },
+            (Some(speed), None) if speed > 0.0 => Timing::Original { speed },
+            _ => return Err(eyre!("replay speed and rate must be more than 0")),
+        };
+
+        Ok(Self {
+            timing,
+            first: None,
+            sent: 0,
+        })
+    }
+
+    /// Returns how long after the start of the replay the next transaction is due. With the
+    /// original timing, transactions dated before the first one are due straight away.
+    pub fn due(&mut self, transaction: &Transaction) -> Duration {
+        let due = match self.timing {
+            Timing::Original { speed } => {
+                let first = *self.first.get_or_insert(transaction.transaction_date);
+                let since_first = (transaction.transaction_date - first)
+                    .to_std()
+                    .unwrap_or_default();
+                since_first.div_f64(speed)
+            }
+            Timing::Fixed { rate } => Duration::from_secs_f64(self.sent as f64 / rate),
+        };
+        self.sent += 1;
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use chrono::Duration as ChronoDuration;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::models::{Currency, TransactionType};
+
+    fn transaction(transaction_date: DateTime<Utc>) -> Transaction {
+        Transaction {
+            transaction_date,
+            merchant_name: "test_slug".to_owned(),
+            transaction_id: "tx_1".to_owned(),
+            identifier: "mid_1".to_owned(),
+            token: "token_1".to_owned(),
+            ..Transaction::fixture()
+        }
+    }
+
+    fn config(speed: Option<f64>, rate: Option<f64>) -> ReplayConfig {
+        ReplayConfig {
+            path: PathBuf::from("replay.jsonl"),
+            speed,
+            rate,
+        }
+    }
+
+    #[test]
+    fn original_timing_is_scaled_by_speed() -> Result<()> {
+        let mut pacer = ReplayPacer::from_config(&config(Some(2.0), None))?;
+        let start = Utc::now();
+
+        assert_eq!(pacer.due(&transaction(start)), Duration::ZERO);
+        assert_eq!(
+            pacer.due(&transaction(start + ChronoDuration::seconds(10))),
+            Duration::from_secs(5)
+        );
+        assert_eq!(
+            pacer.due(&transaction(start - ChronoDuration::seconds(10))),
+            Duration::ZERO
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn fixed_rate_ignores_the_original_timing() -> Result<()> {
+        let mut pacer = ReplayPacer::from_config(&config(None, Some(4.0)))?;
+        let start = Utc::now();
+
+        assert_eq!(pacer.due(&transaction(start)), Duration::ZERO);
+        assert_eq!(
+            pacer.due(&transaction(start + ChronoDuration::hours(1))),
+            Duration::from_millis(250)
+        );
+
+        assert!(ReplayPacer::from_config(&config(Some(1.0), Some(1.0))).is_err());
+        assert!(ReplayPacer::from_config(&config(None, Some(0.0))).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn transactions_are_read_from_jsonl_and_csv() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("atalanta-replay-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+
+        let jsonl = dir.join("replay.jsonl");
+        let date = Utc::now();
+        fs::write(
+            &jsonl,
+            format!("{}\n\n", serde_json::to_string(&transaction(date))?),
+        )?;
+        let transactions = read_transactions(&jsonl)?;
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0].transaction_date, date);
+
+        let csv = dir.join("replay.csv");
+        fs::write(
+            &csv,
+            "amount,transaction_date,payment_provider,merchant_name,transaction_id,auth_code,identifier,identifier_type,token,first_six,last_four,transaction_type,original_transaction_id,currency\n\
+             -245,2024-05-01T10:00:00Z,visa,test_slug,tx_2,123456,mid_1,PRIMARY,token_1,123456,7890,Refund,tx_1,EUR\n",
+        )?;
+        let transactions = read_transactions(&csv)?;
+        assert_eq!(transactions[0].transaction_type, TransactionType::Refund);
+        assert_eq!(
+            transactions[0].original_transaction_id.as_deref(),
+            Some("tx_1")
+        );
+        assert_eq!(transactions[0].currency, Currency::EUR);
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
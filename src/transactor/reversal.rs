@@ -0,0 +1,241 @@
+use std::collections::{HashMap, VecDeque};
+
+use chrono::{DateTime, Utc};
+use color_eyre::{eyre::eyre, Result};
+use rand::Rng;
+use uuid::Builder;
+
+use crate::models::{RetailerConfig, Transaction, TransactionType};
+
+/// How many recent purchases per retailer are kept to be refunded or voided.
+const RECENT_PURCHASES: usize = 1000;
+
+struct RetailerReversals {
+    refund_rate: f64,
+    partial_refund_rate: f64,
+    void_rate: f64,
+    purchases: VecDeque<Transaction>,
+}
+
+/// Remembers recent purchases, and turns a configured fraction of each retailer's transactions
+/// into refunds or voids of them.
+pub struct Reversals {
+    retailers: HashMap<String, RetailerReversals>,
+}
+
+impl Reversals {
+    /// # Errors
+    ///
+    /// Returns an error if a retailer's rates are not fractions, or its refund and void rates add
+    /// up to more than 1.
+    pub fn from_config(retailers: &[RetailerConfig]) -> Result<Self> {
+        let retailers = retailers
+            .iter()
+            .map(|config| {
+                let slug = &config.provider_slug;
+                for (name, rate) in [
+                    ("refund_rate", config.refund_rate),
+                    ("partial_refund_rate", config.partial_refund_rate),
+                    ("void_rate", config.void_rate),
+                ] {
+                    if !(0.0..=1.0).contains(&rate) {
+                        return Err(eyre!(
+                            "{name} {rate} for retailer {slug} is not between 0 and 1"
+                        ));
+                    }
+                }
+                if config.refund_rate + config.void_rate > 1.0 {
+                    return Err(eyre!(
+                        "refund_rate and void_rate for retailer {slug} add up to more than 1"
+                    ));
+                }
+
+                Ok((
+                    slug.clone(),
+                    RetailerReversals {
+                        refund_rate: config.refund_rate,
+                        partial_refund_rate: config.partial_refund_rate,
+                        void_rate: config.void_rate,
+                        purchases: VecDeque::new(),
+                    },
+                ))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self { retailers })
+    }
+
+    /// Possibly reverses one of the retailer's recent purchases, dated no earlier than the
+    /// purchase. Each purchase is reversed at most once.
+    pub fn reverse(
+        &mut self,
+        retailer: &str,
+        date: DateTime<Utc>,
+        rng: &mut impl Rng,
+    ) -> Option<Transaction> {
+        let reversals = self.retailers.get_mut(retailer)?;
+        // Without any rates the generator is left untouched, so seeded runs are unchanged.
+        if reversals.refund_rate + reversals.void_rate == 0.0 {
+            return None;
+        }
+
+        let draw = rng.gen::<f64>();
+        let transaction_type = if draw < reversals.refund_rate {
+            TransactionType::Refund
+        } else if draw < reversals.refund_rate + reversals.void_rate {
+            TransactionType::Void
+        } else {
+            return None;
+        };
+
+        let index = rng.gen_range(0..reversals.purchases.len().max(1));
+        let purchase = reversals.purchases.remove(index)?;
+
+        let amount = if transaction_type == TransactionType::Refund
+            && purchase.amount > 1
+            && rng.gen_bool(reversals.partial_refund_rate)
+        {
+            rng.gen_range(1..purchase.amount)
+        } else {
+            purchase.amount
+        };
+
+        Some(Transaction {
+            amount: -amount,
+            transaction_date: date.max(purchase.transaction_date),
+            transaction_id: Builder::from_random_bytes(rng.gen())
+                .into_uuid()
+                .to_string(),
+            // A void cancels the purchase's authorisation, while a refund is authorised anew.
+            auth_code: if transaction_type == TransactionType::Void {
+                purchase.auth_code.clone()
+            } else {
+                format!("{:0>6}", rng.gen_range(9..1_000_000))
+            },
+            transaction_type,
+            original_transaction_id: Some(purchase.transaction_id.clone()),
+            ..purchase
+        })
+    }
+
+    /// Keeps a purchase or settlement so that a later transaction can reverse it.
+    pub fn remember(&mut self, purchase: &Transaction) {
+        let Some(reversals) = self.retailers.get_mut(&purchase.merchant_name) else {
+            return;
+        };
+        if reversals.refund_rate + reversals.void_rate == 0.0 || purchase.amount <= 0 {
+            return;
+        }
+
+        if reversals.purchases.len() == RECENT_PURCHASES {
+            reversals.purchases.pop_front();
+        }
+        reversals.purchases.push_back(purchase.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use pretty_assertions::assert_eq;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn retailer(refund_rate: f64, partial_refund_rate: f64, void_rate: f64) -> RetailerConfig {
+        RetailerConfig {
+            provider_slug: "test_slug".to_owned(),
+            amount_min: Some(10),
+            amount_max: Some(100),
+            amounts: None,
+            provider_amounts: HashMap::new(),
+            percentage: vec![("visa".to_owned(), 100)],
+            currencies: vec![],
+            identifier_type_percentage: None,
+            weight: None,
+            transactions_per_second: None,
+            refund_rate,
+            partial_refund_rate,
+            void_rate,
+            settlement: None,
+            timestamps: None,
+        }
+    }
+
+    fn purchase(date: DateTime<Utc>) -> Transaction {
+        Transaction {
+            transaction_date: date,
+            merchant_name: "test_slug".to_owned(),
+            transaction_id: "purchase_1".to_owned(),
+            identifier: "mid_1".to_owned(),
+            token: "token_1".to_owned(),
+            ..Transaction::fixture()
+        }
+    }
+
+    #[test]
+    fn refunds_reference_the_purchase_once() -> Result<()> {
+        let mut reversals = Reversals::from_config(&[retailer(1.0, 0.0, 0.0)])?;
+        let mut rng = StdRng::seed_from_u64(1);
+        let date = Utc::now();
+
+        assert!(reversals.reverse("test_slug", date, &mut rng).is_none());
+
+        reversals.remember(&purchase(date));
+        let refund = reversals
+            .reverse("test_slug", date - Duration::hours(1), &mut rng)
+            .expect("a refund of the purchase");
+
+        assert_eq!(refund.transaction_type, TransactionType::Refund);
+        assert_eq!(
+            refund.original_transaction_id.as_deref(),
+            Some("purchase_1")
+        );
+        assert_eq!(refund.amount, -245);
+        assert_eq!(refund.token, "token_1");
+        assert_eq!(refund.transaction_date, date);
+        assert!(refund.transaction_id != "purchase_1");
+
+        assert!(reversals.reverse("test_slug", date, &mut rng).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn partial_refunds_return_less_than_the_purchase() -> Result<()> {
+        let mut reversals = Reversals::from_config(&[retailer(1.0, 1.0, 0.0)])?;
+        let mut rng = StdRng::seed_from_u64(1);
+
+        for _ in 0..100 {
+            reversals.remember(&purchase(Utc::now()));
+            let refund = reversals
+                .reverse("test_slug", Utc::now(), &mut rng)
+                .expect("a refund of the purchase");
+            assert!((-244..0).contains(&refund.amount));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn voids_keep_the_auth_code() -> Result<()> {
+        let mut reversals = Reversals::from_config(&[retailer(0.0, 0.0, 1.0)])?;
+        reversals.remember(&purchase(Utc::now()));
+
+        let void = reversals
+            .reverse("test_slug", Utc::now(), &mut StdRng::seed_from_u64(1))
+            .expect("a void of the purchase");
+
+        assert_eq!(void.transaction_type, TransactionType::Void);
+        assert_eq!(void.auth_code, "123456");
+        assert_eq!(void.amount, -245);
+
+        Ok(())
+    }
+
+    #[test]
+    fn rates_over_one_are_rejected() {
+        assert!(Reversals::from_config(&[retailer(0.6, 0.0, 0.6)]).is_err());
+    }
+}
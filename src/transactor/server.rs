@@ -0,0 +1,40 @@
+use std::net::SocketAddr;
+
+use axum::Router;
+use color_eyre::{eyre::eyre, Result};
+use tracing::{info, warn};
+
+/// Serves `app` from a background thread for as long as the transactor runs, so that the
+/// transactor's own threads are left to generate and publish.
+///
+/// # Errors
+///
+/// Returns an error if the address cannot be bound or the thread cannot be started.
+pub fn spawn(name: &str, address: SocketAddr, app: Router) -> Result<()> {
+    // Bind up front, so that a taken address stops the transactor before it starts publishing.
+    let listener = std::net::TcpListener::bind(address)
+        .map_err(|e| eyre!("failed to serve {name} on {address}: {e}"))?;
+    listener.set_nonblocking(true)?;
+    info!(addr = %address, "serving {name}");
+
+    let thread_name = name.to_owned();
+    std::thread::Builder::new()
+        .name(name.to_owned())
+        .spawn(move || {
+            if let Err(e) = run(listener, app) {
+                warn!("{thread_name} server stopped: {e}");
+            }
+        })?;
+
+    Ok(())
+}
+
+fn run(listener: std::net::TcpListener, app: Router) -> Result<()> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(async move {
+        axum::serve(tokio::net::TcpListener::from_std(listener)?, app).await?;
+        Ok(())
+    })
+}
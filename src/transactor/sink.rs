@@ -0,0 +1,368 @@
+use std::collections::HashSet;
+use std::fs::OpenOptions;
+use std::io::{self, BufWriter, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use amiquip::{
+    AmqpProperties, Channel, Confirm, ConfirmSmoother, Connection, ExchangeDeclareOptions,
+    ExchangeType, Publish, Return,
+};
+use color_eyre::{eyre::eyre, Result};
+use crossbeam_channel::Receiver;
+use serde::Serialize;
+use tracing::warn;
+
+use super::metrics;
+use crate::amqp;
+use crate::models::{Settings, SinkConfig, Transaction};
+
+/// Somewhere to send transactions. Each publish worker has a sink of its own.
+pub trait Sink: Send {
+    /// # Errors
+    ///
+    /// Returns an error if the transaction could not be sent.
+    fn publish(&mut self, transaction: &Transaction, routing_key: &str) -> Result<()>;
+
+    /// Sends anything still buffered.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if buffered transactions could not be sent.
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The AMQP delivery mode that has the broker write messages to disk.
+const PERSISTENT: u8 = 2;
+
+/// The longest a publisher waits for the broker to confirm a message.
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Counts of messages the broker did not take, shared by every AMQP sink in a run.
+#[derive(Default)]
+pub struct PublishStats {
+    /// Messages returned because no queue is bound for their routing key.
+    pub unroutable: AtomicU64,
+    /// Messages the broker nacked.
+    pub rejected: AtomicU64,
+    unroutable_keys: Mutex<HashSet<String>>,
+}
+
+impl PublishStats {
+    /// Counts an unroutable message, and warns the first time its routing key has no queue.
+    /// Returns whether the routing key was new.
+    pub fn record_unroutable(&self, routing_key: &str) -> bool {
+        self.unroutable.fetch_add(1, Ordering::Relaxed);
+        metrics::record_error("unroutable");
+        let mut keys = self
+            .unroutable_keys
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        if keys.contains(routing_key) {
+            return false;
+        }
+        warn!(
+            routing_key,
+            "no queue is bound for routing key, so its transactions are being dropped"
+        );
+        keys.insert(routing_key.to_owned());
+        true
+    }
+
+    pub fn record_rejected(&self) {
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+        metrics::record_error("rejected");
+    }
+}
+
+/// Publishes to the `transactions` topic exchange as `MessagePack`, for the distributors.
+///
+/// Messages are persistent and mandatory, and publisher confirms are enabled with no more than
+/// `max_in_flight` messages left unconfirmed at once.
+pub struct AmqpSink {
+    channel: Channel,
+    confirms: Receiver<Confirm>,
+    returns: Receiver<Return>,
+    smoother: ConfirmSmoother,
+    in_flight: usize,
+    max_in_flight: usize,
+    stats: Arc<PublishStats>,
+}
+
+impl AmqpSink {
+    /// Declares the `transactions` exchange on the channel and enables publisher confirms.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the exchange cannot be declared or confirms cannot be enabled.
+    pub fn new(channel: Channel, max_in_flight: usize, stats: Arc<PublishStats>) -> Result<Self> {
+        channel.exchange_declare(
+            ExchangeType::Topic,
+            "transactions",
+            ExchangeDeclareOptions::default(),
+        )?;
+        // Listen before enabling confirms, so that none are missed.
+        let confirms = channel.listen_for_publisher_confirms()?;
+        let returns = channel.listen_for_returns()?;
+        channel.enable_publisher_confirms()?;
+
+        Ok(Self {
+            channel,
+            confirms,
+            returns,
+            smoother: ConfirmSmoother::new(),
+            in_flight: 0,
+            max_in_flight: max_in_flight.max(1),
+            stats,
+        })
+    }
+
+    /// Waits for confirms until no more than `limit` messages are unconfirmed.
+    fn await_confirms(&mut self, limit: usize) -> Result<()> {
+        while self.in_flight > limit {
+            let confirm = self
+                .confirms
+                .recv_timeout(CONFIRM_TIMEOUT)
+                .map_err(|e| eyre!("no publisher confirm from RabbitMQ: {e}"))?;
+            for confirm in self.smoother.process(confirm) {
+                self.in_flight -= 1;
+                if let Confirm::Nack(_) = confirm {
+                    self.stats.record_rejected();
+                }
+            }
+        }
+
+        // The broker returns an unroutable message before confirming it.
+        for returned in self.returns.try_iter() {
+            self.stats.record_unroutable(&returned.routing_key);
+        }
+
+        Ok(())
+    }
+}
+
+impl Sink for AmqpSink {
+    fn publish(&mut self, transaction: &Transaction, routing_key: &str) -> Result<()> {
+        let body = rmp_serde::to_vec(transaction)?;
+        let mut publish = Publish::with_properties(
+            &body,
+            routing_key,
+            AmqpProperties::default().with_delivery_mode(PERSISTENT),
+        );
+        publish.mandatory = true;
+        self.channel.basic_publish("transactions", publish)?;
+        self.in_flight += 1;
+
+        self.await_confirms(self.max_in_flight - 1)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.await_confirms(0)
+    }
+}
+
+/// A transaction as written by a [`JsonLinesSink`]. The extra field is ignored when the file is
+/// read back as transactions, so the output can be replayed.
+#[derive(Serialize)]
+struct JsonLine<'a> {
+    routing_key: &'a str,
+    #[serde(flatten)]
+    transaction: &'a Transaction,
+}
+
+/// Writes a JSON object per transaction, with its routing key, to a writer shared by every
+/// worker.
+pub struct JsonLinesSink<W> {
+    writer: Arc<Mutex<W>>,
+}
+
+impl<W> Clone for JsonLinesSink<W> {
+    fn clone(&self) -> Self {
+        Self {
+            writer: Arc::clone(&self.writer),
+        }
+    }
+}
+
+impl<W: Write + Send> JsonLinesSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: Arc::new(Mutex::new(writer)),
+        }
+    }
+
+    /// The shared writer, for reading back what was written.
+    #[must_use]
+    pub fn writer(&self) -> Arc<Mutex<W>> {
+        Arc::clone(&self.writer)
+    }
+}
+
+impl<W: Write + Send> Sink for JsonLinesSink<W> {
+    fn publish(&mut self, transaction: &Transaction, routing_key: &str) -> Result<()> {
+        let mut line = serde_json::to_vec(&JsonLine {
+            routing_key,
+            transaction,
+        })?;
+        line.push(b'\n');
+        self.writer
+            .lock()
+            .map_err(|_| eyre!("sink lock poisoned"))?
+            .write_all(&line)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer
+            .lock()
+            .map_err(|_| eyre!("sink lock poisoned"))?
+            .flush()?;
+        Ok(())
+    }
+}
+
+/// The sinks for a run's publish workers, along with the broker connection they share, if any.
+pub struct Sinks {
+    connection: Option<Connection>,
+    stats: Arc<PublishStats>,
+    pub sinks: Vec<Box<dyn Sink>>,
+}
+
+impl Sinks {
+    /// Opens a sink per worker. Only the AMQP sink connects to `RabbitMQ`, and leaves up to
+    /// `max_in_flight` messages per worker unconfirmed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the broker cannot be reached or the file cannot be opened.
+    pub fn open(
+        config: &SinkConfig,
+        settings: &Settings,
+        workers: usize,
+        max_in_flight: usize,
+    ) -> Result<Self> {
+        let workers = workers.max(1);
+        match config {
+            SinkConfig::Amqp => {
+                let mut connection = amqp::connect(settings)?;
+                let stats = Arc::new(PublishStats::default());
+                // Each worker publishes on its own channel - None says let the library choose
+                // the channel ID.
+                let sinks = (0..workers)
+                    .map(|_| {
+                        let channel = connection.open_channel(None)?;
+                        let sink = AmqpSink::new(channel, max_in_flight, Arc::clone(&stats))?;
+                        Ok(Box::new(sink) as Box<dyn Sink>)
+                    })
+                    .collect::<Result<_>>()?;
+                Ok(Self {
+                    connection: Some(connection),
+                    stats,
+                    sinks,
+                })
+            }
+            SinkConfig::File { path } => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(|e| eyre!("failed to open sink file {}: {e}", path.display()))?;
+                Ok(Self::shared(
+                    &JsonLinesSink::new(BufWriter::new(file)),
+                    workers,
+                ))
+            }
+            SinkConfig::Stdout => Ok(Self::shared(&JsonLinesSink::new(io::stdout()), workers)),
+        }
+    }
+
+    fn shared<W: Write + Send + 'static>(sink: &JsonLinesSink<W>, workers: usize) -> Self {
+        Self {
+            connection: None,
+            stats: Arc::default(),
+            sinks: (0..workers)
+                .map(|_| Box::new(sink.clone()) as Box<dyn Sink>)
+                .collect(),
+        }
+    }
+
+    /// Closes the broker connection, if there is one, and warns about any messages the broker
+    /// did not take.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection does not close cleanly.
+    pub fn close(self) -> Result<()> {
+        let unroutable = self.stats.unroutable.load(Ordering::Relaxed);
+        let rejected = self.stats.rejected.load(Ordering::Relaxed);
+        if unroutable > 0 || rejected > 0 {
+            warn!(
+                unroutable,
+                rejected, "RabbitMQ did not deliver every published transaction"
+            );
+        }
+
+        if let Some(connection) = self.connection {
+            connection.close()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn unroutable_routing_keys_are_warned_about_once() {
+        let stats = PublishStats::default();
+
+        assert!(stats.record_unroutable("transactions.visa.costa"));
+        assert!(!stats.record_unroutable("transactions.visa.costa"));
+        assert!(stats.record_unroutable("transactions.amex.costa"));
+        stats.record_rejected();
+
+        assert_eq!(stats.unroutable.load(Ordering::Relaxed), 3);
+        assert_eq!(stats.rejected.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn json_lines_sink_writes_replayable_lines() -> Result<()> {
+        let transaction = Transaction {
+            merchant_name: "test_slug".to_owned(),
+            transaction_id: "tx_1".to_owned(),
+            identifier: "mid_1".to_owned(),
+            token: "token_1".to_owned(),
+            ..Transaction::fixture()
+        };
+        let sink = JsonLinesSink::new(Vec::new());
+        let mut workers = [sink.clone(), sink.clone()];
+
+        for worker in &mut workers {
+            worker.publish(&transaction, &transaction.routing_key())?;
+            worker.flush()?;
+        }
+
+        let written = String::from_utf8(
+            sink.writer()
+                .lock()
+                .map_err(|_| eyre!("sink lock poisoned"))?
+                .clone(),
+        )?;
+        let lines = written.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+
+        let value = serde_json::from_str::<serde_json::Value>(lines[0])?;
+        assert_eq!(value["routing_key"], "transactions.visa.test_slug");
+        let replayed = serde_json::from_str::<Transaction>(lines[1])?;
+        assert_eq!(replayed.transaction_id, "tx_1");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use hdrhistogram::Histogram;
+
+use super::injection::Injection;
+use crate::models::Transaction;
+
+/// Amount percentiles reported in the summary.
+const AMOUNT_PERCENTILES: [f64; 5] = [50.0, 90.0, 95.0, 99.0, 100.0];
+
+/// Counts of the transactions generated in a run, broken down by retailer, payment provider, MID,
+/// token, transaction type and injected fault, along with the distribution of their amounts.
+pub struct RunSummary {
+    pub total: u64,
+    pub by_retailer: BTreeMap<String, u64>,
+    pub by_payment_provider: BTreeMap<String, u64>,
+    pub by_identifier: BTreeMap<String, u64>,
+    pub by_token: BTreeMap<String, u64>,
+    pub by_transaction_type: BTreeMap<String, u64>,
+    pub by_injection: BTreeMap<String, u64>,
+    pub amounts: Histogram<u64>,
+    /// Amounts left out of `amounts`, which only holds positive values.
+    pub unrecorded_amounts: u64,
+}
+
+impl Default for RunSummary {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RunSummary {
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)] // the histogram precision is a valid constant
+    pub fn new() -> Self {
+        Self {
+            total: 0,
+            by_retailer: BTreeMap::new(),
+            by_payment_provider: BTreeMap::new(),
+            by_identifier: BTreeMap::new(),
+            by_token: BTreeMap::new(),
+            by_transaction_type: BTreeMap::new(),
+            by_injection: BTreeMap::new(),
+            // three significant figures is plenty for amounts, and the histogram grows as needed
+            amounts: Histogram::new(3)
+                .expect("3 significant figures is a valid histogram precision"),
+            unrecorded_amounts: 0,
+        }
+    }
+
+    pub fn record(&mut self, transaction: &Transaction, injection: Option<Injection>) {
+        self.total += 1;
+        increment(&mut self.by_retailer, &transaction.merchant_name);
+        increment(&mut self.by_payment_provider, &transaction.payment_provider);
+        increment(&mut self.by_identifier, &transaction.identifier);
+        increment(&mut self.by_token, &transaction.token);
+        increment(
+            &mut self.by_transaction_type,
+            &transaction.transaction_type.to_string(),
+        );
+        if let Some(injection) = injection {
+            increment(&mut self.by_injection, &injection.to_string());
+        }
+
+        // Only positive amounts fit in the histogram. It resizes to fit larger amounts, where
+        // saturating_record would clamp them to its initial range.
+        let recorded = u64::try_from(transaction.amount)
+            .ok()
+            .filter(|amount| *amount > 0)
+            .is_some_and(|amount| self.amounts.record(amount).is_ok());
+        if !recorded {
+            self.unrecorded_amounts += 1;
+        }
+    }
+}
+
+fn increment(counts: &mut BTreeMap<String, u64>, key: &str) {
+    if let Some(count) = counts.get_mut(key) {
+        *count += 1;
+    } else {
+        counts.insert(key.to_owned(), 1);
+    }
+}
+
+impl fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "total transactions: {}", self.total)?;
+        for (title, counts) in [
+            ("retailer", &self.by_retailer),
+            ("payment provider", &self.by_payment_provider),
+            ("MID", &self.by_identifier),
+            ("token", &self.by_token),
+            ("transaction type", &self.by_transaction_type),
+        ] {
+            writeln!(f, "\nper {title}:")?;
+            for (key, count) in counts {
+                writeln!(f, "  {key}: {count}")?;
+            }
+        }
+
+        if !self.by_injection.is_empty() {
+            writeln!(f, "\nper injected fault (should not match):")?;
+            for (injection, count) in &self.by_injection {
+                writeln!(f, "  {injection}: {count}")?;
+            }
+        }
+
+        if !self.amounts.is_empty() {
+            writeln!(f, "\namount percentiles (minor units):")?;
+            for percentile in AMOUNT_PERCENTILES {
+                writeln!(
+                    f,
+                    "  p{percentile}: {}",
+                    self.amounts.value_at_percentile(percentile)
+                )?;
+            }
+        }
+        if self.unrecorded_amounts > 0 {
+            writeln!(
+                f,
+                "  {} zero, negative or out of range amounts left out",
+                self.unrecorded_amounts
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::models::TransactionType;
+
+    fn transaction(payment_provider: &str, identifier: &str, token: &str) -> Transaction {
+        Transaction {
+            payment_provider: payment_provider.to_owned(),
+            identifier: identifier.to_owned(),
+            token: token.to_owned(),
+            ..Transaction::fixture()
+        }
+    }
+
+    #[test]
+    fn summary_counts_each_breakdown() {
+        let mut summary = RunSummary::new();
+        summary.record(&transaction("visa", "mid_1", "token_1"), None);
+        summary.record(
+            &transaction("visa", "mid_2", "token_1"),
+            Some(Injection::UnknownMid),
+        );
+        summary.record(&transaction("amex", "mid_2", "token_2"), None);
+        summary.record(
+            &Transaction {
+                amount: 125_000,
+                ..transaction("amex", "mid_3", "token_3")
+            },
+            None,
+        );
+        summary.record(
+            &Transaction {
+                amount: -245,
+                transaction_type: TransactionType::Refund,
+                ..transaction("mastercard", "mid_4", "token_4")
+            },
+            None,
+        );
+
+        assert_eq!(summary.total, 5);
+        assert_eq!(summary.by_payment_provider["visa"], 2);
+        assert_eq!(summary.by_payment_provider["amex"], 2);
+        assert_eq!(summary.by_identifier["mid_2"], 2);
+        assert_eq!(summary.by_token["token_1"], 2);
+        assert_eq!(summary.by_transaction_type["purchase"], 4);
+        assert_eq!(summary.by_transaction_type["refund"], 1);
+        assert_eq!(summary.by_injection["unknown_mid"], 1);
+        assert_eq!(summary.amounts.len(), 4);
+        assert_eq!(summary.unrecorded_amounts, 1);
+        assert_eq!(summary.amounts.value_at_percentile(50.0), 245);
+        assert!(summary
+            .amounts
+            .equivalent(summary.amounts.value_at_percentile(100.0), 125_000));
+    }
+}
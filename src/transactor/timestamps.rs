@@ -0,0 +1,274 @@
+use chrono::{
+    DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeZone, Utc,
+};
+use chrono_tz::Tz;
+use color_eyre::{eyre::eyre, Result};
+use rand::Rng;
+
+use crate::models::TimestampConfig;
+
+/// Years searched for DST transitions, which covers any plausible simulated start date.
+const TRANSITION_YEARS: std::ops::Range<i32> = 1970..2100;
+
+/// Moves transaction dates into a retailer's trading hours, backdates them, and places some of
+/// them either side of a midnight or DST boundary in the retailer's local timezone.
+pub struct TimestampShaper {
+    timezone: Tz,
+    opening_hours: Option<(NaiveTime, NaiveTime)>,
+    lag: Duration,
+    lag_jitter_secs: i64,
+    boundary_rate: f64,
+    boundary_window_secs: i64,
+    transitions: Vec<DateTime<Utc>>,
+}
+
+impl TimestampShaper {
+    /// # Errors
+    ///
+    /// Returns an error if the timezone is unknown or the boundary rate is not a fraction.
+    pub fn from_config(config: &TimestampConfig) -> Result<Self> {
+        let timezone = config
+            .timezone
+            .parse::<Tz>()
+            .map_err(|e| eyre!("unknown timezone {}: {e}", config.timezone))?;
+        if !(0.0..=1.0).contains(&config.boundary_rate) {
+            return Err(eyre!(
+                "boundary_rate {} is not between 0 and 1",
+                config.boundary_rate
+            ));
+        }
+
+        Ok(Self {
+            timezone,
+            opening_hours: config.opening_hours,
+            lag: Duration::seconds(config.lag_secs.into()),
+            lag_jitter_secs: config.lag_jitter_secs.into(),
+            boundary_rate: config.boundary_rate,
+            boundary_window_secs: config.boundary_window_secs.into(),
+            transitions: dst_transitions(timezone),
+        })
+    }
+
+    /// Returns the date to give a transaction generated at `date`. The result is never later
+    /// than `date`.
+    pub fn shape(&self, date: DateTime<Utc>, rng: &mut impl Rng) -> DateTime<Utc> {
+        let lagged = date - self.lag - Duration::seconds(rng.gen_range(0..=self.lag_jitter_secs));
+
+        if self.boundary_rate > 0.0 && rng.gen_bool(self.boundary_rate) {
+            self.near_boundary(lagged, rng).min(date)
+        } else {
+            self.within_opening_hours(lagged, rng)
+        }
+    }
+
+    /// Leaves a date in trading hours alone, and otherwise picks a time in the most recent
+    /// opening period before it.
+    fn within_opening_hours(&self, date: DateTime<Utc>, rng: &mut impl Rng) -> DateTime<Utc> {
+        let Some((open, close)) = self.opening_hours else {
+            return date;
+        };
+
+        // Equal opening and closing times mean the store never closes.
+        let length = if close > open {
+            close - open
+        } else {
+            close - open + Duration::days(1)
+        };
+
+        let local = date.with_timezone(&self.timezone).naive_local();
+        let mut opened = local.date().and_time(open);
+        if opened > local {
+            opened -= Duration::days(1);
+        }
+        if local < opened + length {
+            return date;
+        }
+
+        self.to_utc(opened + Duration::seconds(rng.gen_range(0..length.num_seconds())))
+    }
+
+    /// Picks a time around the local midnight or DST transition before `date`.
+    fn near_boundary(&self, date: DateTime<Utc>, rng: &mut impl Rng) -> DateTime<Utc> {
+        let transition = self.transitions.iter().rev().find(|t| **t <= date);
+        let boundary = match transition {
+            Some(transition) if rng.gen_bool(0.5) => *transition,
+            _ => {
+                let local = date.with_timezone(&self.timezone).date_naive();
+                self.to_utc(local.and_time(NaiveTime::MIN))
+            }
+        };
+
+        boundary
+            + Duration::seconds(
+                rng.gen_range(-self.boundary_window_secs..=self.boundary_window_secs),
+            )
+    }
+
+    /// Converts a local time to UTC. Times skipped by a DST change are moved an hour later, and
+    /// times repeated by one take their first occurrence.
+    fn to_utc(&self, local: NaiveDateTime) -> DateTime<Utc> {
+        self.timezone
+            .from_local_datetime(&local)
+            .earliest()
+            .or_else(|| {
+                self.timezone
+                    .from_local_datetime(&(local + Duration::hours(1)))
+                    .earliest()
+            })
+            .map_or_else(|| local.and_utc(), |date| date.with_timezone(&Utc))
+    }
+}
+
+/// Finds every change of UTC offset in a timezone, to the second.
+fn dst_transitions(timezone: Tz) -> Vec<DateTime<Utc>> {
+    let offset = |date: DateTime<Utc>| timezone.offset_from_utc_datetime(&date.naive_utc()).fix();
+
+    let mut transitions = Vec::new();
+    let Some(mut day) = NaiveDate::from_yo_opt(TRANSITION_YEARS.start, 1) else {
+        return transitions;
+    };
+    let mut from = day.and_time(NaiveTime::MIN).and_utc();
+    while day.year() < TRANSITION_YEARS.end {
+        day += Duration::days(1);
+        let to = day.and_time(NaiveTime::MIN).and_utc();
+        if offset(from) != offset(to) {
+            // The offset changes somewhere in (from, to], so narrow down on it.
+            let (mut before, mut after) = (from, to);
+            while after - before > Duration::seconds(1) {
+                let middle = before + Duration::seconds((after - before).num_seconds() / 2);
+                if offset(middle) == offset(before) {
+                    before = middle;
+                } else {
+                    after = middle;
+                }
+            }
+            transitions.push(after);
+        }
+        from = to;
+    }
+    transitions
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn config() -> TimestampConfig {
+        TimestampConfig {
+            timezone: "Europe/London".to_owned(),
+            opening_hours: None,
+            lag_secs: 0,
+            lag_jitter_secs: 0,
+            boundary_rate: 0.0,
+            boundary_window_secs: 300,
+        }
+    }
+
+    fn time(hour: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, 0, 0).expect("valid test time")
+    }
+
+    fn utc(date: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(date)
+            .expect("valid test date")
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn dates_are_backdated_by_the_lag() -> Result<()> {
+        let shaper = TimestampShaper::from_config(&TimestampConfig {
+            lag_secs: 3600,
+            ..config()
+        })?;
+        let date = utc("2024-06-01T12:00:00Z");
+
+        assert_eq!(
+            shaper.shape(date, &mut StdRng::seed_from_u64(1)),
+            utc("2024-06-01T11:00:00Z")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn closed_hours_move_into_the_last_opening_period() -> Result<()> {
+        let shaper = TimestampShaper::from_config(&TimestampConfig {
+            opening_hours: Some((time(8), time(22))),
+            ..config()
+        })?;
+        let mut rng = StdRng::seed_from_u64(1);
+
+        // 23:30 BST is after closing, so transactions fall between 08:00 and 22:00 that day.
+        let closed = utc("2024-06-01T22:30:00Z");
+        for _ in 0..100 {
+            let date = shaper.shape(closed, &mut rng);
+            assert!(date >= utc("2024-06-01T07:00:00Z"));
+            assert!(date < utc("2024-06-01T21:00:00Z"));
+        }
+
+        let open = utc("2024-06-01T12:00:00Z");
+        assert_eq!(shaper.shape(open, &mut rng), open);
+
+        Ok(())
+    }
+
+    #[test]
+    fn opening_hours_may_run_past_midnight() -> Result<()> {
+        let shaper = TimestampShaper::from_config(&TimestampConfig {
+            opening_hours: Some((time(18), time(2))),
+            ..config()
+        })?;
+        let late = utc("2024-01-02T01:00:00Z");
+
+        assert_eq!(shaper.shape(late, &mut StdRng::seed_from_u64(1)), late);
+
+        Ok(())
+    }
+
+    #[test]
+    fn london_transitions_are_found() {
+        let transitions = dst_transitions(chrono_tz::Europe::London);
+
+        assert!(transitions.contains(&utc("2024-03-31T01:00:00Z")));
+        assert!(transitions.contains(&utc("2024-10-27T01:00:00Z")));
+    }
+
+    #[test]
+    fn boundary_dates_cluster_around_midnight_and_transitions() -> Result<()> {
+        let shaper = TimestampShaper::from_config(&TimestampConfig {
+            boundary_rate: 1.0,
+            ..config()
+        })?;
+        let mut rng = StdRng::seed_from_u64(1);
+        let date = utc("2024-11-01T12:00:00Z");
+        let boundaries = [utc("2024-11-01T00:00:00Z"), utc("2024-10-27T01:00:00Z")];
+
+        let dates = (0..100)
+            .map(|_| shaper.shape(date, &mut rng))
+            .collect::<Vec<_>>();
+        let near = |date: &DateTime<Utc>, boundary: &DateTime<Utc>| {
+            (*date - *boundary).num_seconds().abs() <= 300
+        };
+        for boundary in &boundaries {
+            assert!(dates.iter().any(|date| near(date, boundary)));
+        }
+        assert!(dates
+            .iter()
+            .all(|date| boundaries.iter().any(|boundary| near(date, boundary))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_timezone_is_rejected() {
+        let config = TimestampConfig {
+            timezone: "Europe/Atlantis".to_owned(),
+            ..config()
+        };
+        assert!(TimestampShaper::from_config(&config).is_err());
+    }
+}